@@ -0,0 +1,37 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use packet::udp::dhcp::duid::DhcpDuidConfig;
+use packet::udp::dhcp::resolver::DhcpResolverConfig;
+use serde::Deserialize;
+
+pub const CONFIG_FILE: &str = "/etc/inode/inode.json";
+
+// The config file, e.g.
+// {"resolver": {"backend": "systemd-resolved"}, "hook": "/etc/inode/hook",
+//  "max-retransmits": 8}
+// A missing file or section keeps the defaults.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Config {
+    pub resolver: Option<DhcpResolverConfig>,
+    // dhclient-script compatible executable run on lease changes
+    pub hook: Option<PathBuf>,
+    // RFC 4361 client identifier from a DUID instead of the MAC
+    pub duid: Option<DhcpDuidConfig>,
+    // retransmissions before an exchange gives up, see
+    // DhcpClientConfig::max_retransmits
+    pub max_retransmits: Option<u32>,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self> {
+        let bytes = match fs::read(path) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Config::default()),
+            result => result.with_context(|| format!("reading {}", path.display()))?,
+        };
+        serde_json::from_slice(&bytes).with_context(|| format!("parsing {}", path.display()))
+    }
+}
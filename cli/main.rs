@@ -1,132 +1,365 @@
+mod config;
+
+use std::net::Ipv4Addr;
+use std::path::PathBuf;
+use std::time::Duration;
+
 use anyhow::Result;
-use bytes::{Bytes, BytesMut};
-use packet::udp::dhcp::*;
-use pnet::datalink;
-use tokio::{self, net::UdpSocket};
+use clap::{ArgGroup, Parser, Subcommand};
+use config::{Config, CONFIG_FILE};
+use packet::udp::dhcp::client::*;
+use packet::udp::dhcp::duid::{client_identifier, DhcpDuid};
+use packet::udp::dhcp::lease::*;
+use packet::udp::dhcp::resolver::DhcpResolverConfig;
+use packet::udp::dhcp::supervisor::{DhcpSupervisor, DhcpTransportKind};
+use packet::udp::dhcp::transport::DhcpUdpTransport;
+use pnet::datalink::{self, NetworkInterface};
+use pnet::util::MacAddr;
+
+#[derive(Debug, Parser)]
+#[command(name = "inode")]
+struct Cli {
+    /// JSON config file
+    #[arg(long, global = true, default_value = CONFIG_FILE)]
+    config: PathBuf,
+    /// Retransmissions before an exchange gives up, overrides the config file
+    #[arg(long, global = true)]
+    max_retransmits: Option<u32>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    Dhcp {
+        #[command(subcommand)]
+        command: DhcpCommand,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum DhcpCommand {
+    /// Acquire and hold a lease, one client per interface
+    #[command(group(ArgGroup::new("interfaces").args(["iface", "all"])))]
+    Run {
+        /// Interface to run a client on, may be repeated
+        #[arg(long)]
+        iface: Vec<String>,
+        /// Every interface that is up, has a MAC and is not loopback
+        #[arg(long, conflicts_with = "iface")]
+        all: bool,
+        /// Hardware address to use instead of the interface's own
+        #[arg(long, value_parser = parse_mac, conflicts_with = "all")]
+        mac: Option<MacAddr>,
+        #[arg(long, default_value = DHCP_LEASE_DIR)]
+        lease_dir: PathBuf,
+        /// Send and receive raw frames, for a NIC without an address
+        #[arg(long, requires = "interfaces")]
+        raw: bool,
+        /// Configure address, default route and MTU of the interfaces from the lease
+        #[arg(long, requires = "interfaces")]
+        configure: bool,
+        #[command(flatten)]
+        offers: OfferArgs,
+    },
+    /// Ask for configuration without a lease, for statically addressed hosts
+    Inform {
+        #[arg(long)]
+        iface: Option<String>,
+        /// Hardware address to use instead of the interface's own
+        #[arg(long, value_parser = parse_mac)]
+        mac: Option<MacAddr>,
+        #[arg(long)]
+        address: Ipv4Addr,
+    },
+    /// Release the persisted lease of an interface
+    Release {
+        #[arg(long)]
+        iface: String,
+        #[arg(long, default_value = DHCP_LEASE_DIR)]
+        lease_dir: PathBuf,
+        /// Remove the address and default route of the lease from --iface
+        #[arg(long)]
+        configure: bool,
+    },
+}
+
+#[derive(Debug, Default, clap::Args)]
+struct OfferArgs {
+    /// Seconds to collect offers before selecting one
+    #[arg(long, default_value_t = 0)]
+    offer_window: u64,
+    /// Server identifier whose offers are preferred
+    #[arg(long)]
+    prefer_server: Vec<Ipv4Addr>,
+    /// Server identifier whose offers are rejected
+    #[arg(long)]
+    reject_server: Vec<Ipv4Addr>,
+    /// Prefer an offer of the previously held address
+    #[arg(long)]
+    prefer_previous: bool,
+    /// Prefer the offer with the longest lease
+    #[arg(long)]
+    prefer_longest_lease: bool,
+}
+
+impl OfferArgs {
+    fn apply(&self, config: &mut DhcpClientConfig) {
+        config.offer_window = Duration::from_secs(self.offer_window);
+        config.offer_policy = DhcpOfferPolicy {
+            rejected_servers: self.reject_server.clone(),
+            preferred_servers: self.prefer_server.clone(),
+            prefer_previous_address: self.prefer_previous,
+            prefer_longest_lease: self.prefer_longest_lease,
+        };
+    }
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let mut config_file = Config::load(&cli.config)?;
+    if cli.max_retransmits.is_some() {
+        config_file.max_retransmits = cli.max_retransmits;
+    }
+    match cli.command {
+        Some(Command::Dhcp {
+            command:
+                DhcpCommand::Release {
+                    iface,
+                    lease_dir,
+                    configure,
+                },
+        }) => release(&config_file, iface, lease_dir, configure).await,
+        Some(Command::Dhcp {
+            command:
+                DhcpCommand::Inform {
+                    iface,
+                    mac,
+                    address,
+                },
+        }) => inform(&config_file, iface, mac, address).await,
+        Some(Command::Dhcp {
+            command:
+                DhcpCommand::Run {
+                    iface,
+                    all,
+                    mac,
+                    lease_dir,
+                    raw,
+                    configure,
+                    offers,
+                },
+        }) => {
+            run(
+                &config_file,
+                iface,
+                all,
+                mac,
+                lease_dir,
+                raw,
+                configure,
+                offers,
+            )
+            .await
+        }
+        None => {
+            run(
+                &config_file,
+                Vec::new(),
+                false,
+                None,
+                PathBuf::from(DHCP_LEASE_DIR),
+                false,
+                false,
+                OfferArgs::default(),
+            )
+            .await
+        }
+    }
+}
+
+async fn bind(iface: Option<&str>) -> Result<DhcpUdpTransport> {
+    let transport = DhcpUdpTransport::bind(iface)?;
+    println!("开启广播模式");
+    Ok(transport)
+}
+
+// up, not loopback and with a hardware address to use
+fn usable(interface: &NetworkInterface) -> bool {
+    interface.is_up() && !interface.is_loopback() && interface_mac(interface).is_ok()
+}
+
+fn client_config(
+    interface: Option<&NetworkInterface>,
+    mac: Option<MacAddr>,
+) -> Result<DhcpClientConfig> {
+    let config = match (interface, mac) {
+        (Some(interface), None) => DhcpClientConfig::for_interface(interface)?,
+        (Some(interface), Some(mac)) => {
+            let mut config = DhcpClientConfig::new(mac);
+            config.interface = Some(interface.name.clone());
+            config
+        }
+        (None, Some(mac)) => DhcpClientConfig::new(mac),
+        // not bound to an interface, take the address of the first usable one
+        (None, None) => match datalink::interfaces().iter().find(|ele| usable(ele)) {
+            Some(interface) => DhcpClientConfig::new(interface_mac(interface)?),
+            None => anyhow::bail!("没有可用的网卡, 请用 --mac 指定 MAC 地址"),
+        },
+    };
+    println!("MAC: {}", config.mac);
+    Ok(config)
+}
+
+// RFC 4361 client identifier when the config file asks for a DUID
+fn identify(config_file: &Config, config: &mut DhcpClientConfig) -> Result<()> {
+    let Some(duid) = &config_file.duid else {
+        return Ok(());
+    };
+    let id = DhcpDuid::load_or_generate(&duid.file, &duid.tp, config.mac)?;
+    let iaid = duid.iaid(config.interface.as_deref().unwrap_or("default"));
+    config.client_identifier = client_identifier(iaid, &id);
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn run(
+    config_file: &Config,
+    iface: Vec<String>,
+    all: bool,
+    mac: Option<MacAddr>,
+    lease_dir: PathBuf,
+    raw: bool,
+    configure: bool,
+    offers: OfferArgs,
+) -> Result<()> {
     let interfaces = datalink::interfaces();
     println!("网卡数: {}", interfaces.len());
-    for interface in interfaces.into_iter() {
+    for interface in interfaces.iter() {
         println!(
             "网络 - name: {} MAC: {}",
             interface.description,
             interface.mac.unwrap_or_default()
         );
     }
-    let socket = UdpSocket::bind("0.0.0.0:68").await?;
-    let broadcast = socket.set_broadcast(true);
-    if let Ok(r) = broadcast {
-        println!("开启广播模式");
+    if mac.is_some() && iface.len() > 1 {
+        anyhow::bail!("--mac 只能用于一个网卡");
     }
-    let mut buf = [0; 1024];
-    let mac = "F6-6D-3F-C0-8A-6F";
-    let mut v = vec![DHCPHType::ETHERNET as u8];
-    let a: Vec<u8> = str::split(mac, "-")
-        .map(|u| u8::from_str_radix(u, 16).unwrap())
-        .collect();
-    v.extend_from_slice(&a);
-    let parameter_request_list: Vec<u8> = vec![
-        DHCPOPTION::SubnetMask as u8,
-        DHCPOPTION::RouterOption as u8,
-        DHCPOPTION::DomainNameServerOption as u8,
-        DHCPOPTION::NetworkTimeProtocolServersOption as u8,
-    ];
-    let mut discovery_message = DHCPDiscover::with_mac(mac);
-    discovery_message.insert_options(vec![
-        DHCPOPTIONS {
-            tp: DHCPOPTION::DHCPMessageType,
-            len: 1,
-            va: vec![DHCPMessageType::DHCPDISCOVER as u8],
-        },
-        DHCPOPTIONS {
-            tp: DHCPOPTION::ClientIdentifier,
-            len: v.len() as u8,
-            va: v.clone(),
-        },
-        DHCPOPTIONS {
-            tp: DHCPOPTION::ParameterRequestList,
-            len: parameter_request_list.len() as u8,
-            va: parameter_request_list.clone(),
-        },
-    ]);
-    let tmp_bytes = discovery_message.to_bytes();
-
-    let broadcast_result = socket.send_to(&tmp_bytes, "255.255.255.255:67").await;
-    match broadcast_result {
-        Ok(n) => println!("发送数据包: {}", n),
-        Err(e) => println!("错误: {:?}", e),
+    let selected: Vec<_> = if all {
+        interfaces.into_iter().filter(usable).collect()
+    } else {
+        let mut selected = Vec::new();
+        for name in &iface {
+            selected.push(find_interface(name)?);
+        }
+        selected
+    };
+    let shared_file = matches!(
+        config_file.resolver,
+        Some(DhcpResolverConfig::ResolvConf(_))
+    );
+    if shared_file && selected.len() > 1 {
+        anyhow::bail!("resolv-conf 只能用于一个网卡, 多个网卡请用 resolvconf 或 systemd-resolved");
     }
-    loop {
-        println!(">>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>>");
-        println!("BEGIN");
-        let (len, addr) = socket.recv_from(&mut buf).await?;
-        println!("{:?} bytes received from {:?}", len, addr);
-        let dhcp_offer = DHCPOffer::from_bytes(&mut Bytes::from_iter(buf));
-        println!("Prefer IP Address: {:?}", dhcp_offer.yiaddr);
-        let dhcp_server_identifier: Vec<&DHCPOPTIONS> = dhcp_offer
-            .options
-            .iter()
-            .filter(|&v| v.tp == DHCPOPTION::ServerIdentifier)
-            .collect();
-        let mut options = vec![
-            DHCPOPTIONS {
-                tp: DHCPOPTION::DHCPMessageType,
-                len: 1,
-                va: vec![DHCPMessageType::DHCPREQUEST as u8],
-            },
-            DHCPOPTIONS {
-                tp: DHCPOPTION::ClientIdentifier,
-                len: v.len() as u8,
-                va: v.clone(),
-            },
-            DHCPOPTIONS {
-                tp: DHCPOPTION::RequestedIPAddress,
-                len: 4u8,
-                va: dhcp_offer.yiaddr.to_vec(),
-            },
-            // DHCPOPTIONS {
-            //     tp: DHCPOPTION::ServerIdentifier,
-            //     len: 4u8,
-            //     va: dhcp_offer.chaddr.to_vec(),
-            // },
-            DHCPOPTIONS {
-                tp: DHCPOPTION::ParameterRequestList,
-                len: parameter_request_list.len() as u8,
-                va: parameter_request_list.clone(),
-            },
-        ];
-        if dhcp_server_identifier.len() > 0 {
-            let option = dhcp_server_identifier.get(0).unwrap();
-            println!("{:?}", *option);
-            options.insert(
-                0,
-                DHCPOPTIONS {
-                    tp: DHCPOPTION::ServerIdentifier,
-                    len: 4u8,
-                    va: option.va.clone(),
-                },
-            );
+    if selected.is_empty() {
+        if all {
+            anyhow::bail!("没有可用的网卡");
+        }
+        let mut config = client_config(None, mac)?;
+        config.lease_file = Some(lease_path(&lease_dir, "default"));
+        config.resolver = config_file.resolver.clone();
+        config.hook = config_file.hook.clone();
+        if let Some(max_retransmits) = config_file.max_retransmits {
+            config.max_retransmits = max_retransmits;
         }
-        let mut v = vec![DHCPHType::ETHERNET as u8];
-        let a: Vec<u8> = str::split(mac, "-")
-            .map(|u| u8::from_str_radix(u, 16).unwrap())
-            .collect();
-        v.extend_from_slice(&a);
-        let mut dhcp_request = DHCPRequest::with_mac_ip_options(mac, options);
-        println!("DHCP Request: {:?}", dhcp_request);
-        let result = socket
-            .send_to(&dhcp_request.to_bytes(), "255.255.255.255:67")
-            .await;
-        println!("DHCP Request: {:?}", result);
-        // TODO: DHCPACK
-        let (len, addr) = socket.recv_from(&mut buf).await?;
-        println!("{:?} bytes received from {:?}", len, addr);
-        let dhcp_ack = DHCPAck::from_bytes(&mut Bytes::from_iter(buf));
-        println!("DHCP ACK: {:?}", dhcp_ack);
-        break;
-        println!("<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<<");
+        offers.apply(&mut config);
+        identify(config_file, &mut config)?;
+        let mut client = DhcpClient::new(config);
+        let transport = bind(None).await?;
+        client.run(&transport, shutdown_signal()).await?;
+        return Ok(());
     }
+    let transport = if raw {
+        DhcpTransportKind::Raw
+    } else {
+        DhcpTransportKind::Udp
+    };
+    let mut supervisor = DhcpSupervisor::new(transport);
+    for interface in selected {
+        let mut config = client_config(Some(&interface), mac)?;
+        config.lease_file = Some(lease_path(&lease_dir, &interface.name));
+        config.configure_interface = configure;
+        config.resolver = config_file.resolver.clone();
+        config.hook = config_file.hook.clone();
+        if let Some(max_retransmits) = config_file.max_retransmits {
+            config.max_retransmits = max_retransmits;
+        }
+        offers.apply(&mut config);
+        identify(config_file, &mut config)?;
+        supervisor.clients.push(config);
+    }
+    supervisor.run(shutdown_signal()).await;
     Ok(())
 }
+
+async fn inform(
+    config_file: &Config,
+    iface: Option<String>,
+    mac: Option<MacAddr>,
+    address: Ipv4Addr,
+) -> Result<()> {
+    let interface = iface.as_deref().map(find_interface).transpose()?;
+    let mut config = client_config(interface.as_ref(), mac)?;
+    if let Some(max_retransmits) = config_file.max_retransmits {
+        config.max_retransmits = max_retransmits;
+    }
+    identify(config_file, &mut config)?;
+    let transport = bind(iface.as_deref()).await?;
+    let mut client = DhcpClient::new(config);
+    let ack = client.inform(&transport, address).await?;
+    println!("Server: {:?}", ack.server_identifier());
+    println!("Subnet Mask: {:?}", ack.subnet_mask());
+    println!("Routers: {:?}", ack.routers());
+    println!("DNS Servers: {:?}", ack.dns_servers());
+    println!("Domain Name: {:?}", ack.domain_name());
+    println!("NTP Servers: {:?}", ack.ntp_servers());
+    Ok(())
+}
+
+async fn release(
+    config_file: &Config,
+    iface: String,
+    lease_dir: PathBuf,
+    configure: bool,
+) -> Result<()> {
+    let path = lease_path(&lease_dir, &iface);
+    let record = DhcpLeaseRecord::load(&path)?;
+    let mut config = DhcpClientConfig::new(record.mac);
+    let transport = bind(Some(&iface)).await?;
+    config.interface = Some(iface);
+    config.lease_file = Some(path);
+    config.configure_interface = configure;
+    config.resolver = config_file.resolver.clone();
+    config.hook = config_file.hook.clone();
+    identify(config_file, &mut config)?;
+    let mut client = DhcpClient::new(config);
+    client.restore_record(&record);
+    client.release_lease(&transport).await?;
+    Ok(())
+}
+
+#[cfg(unix)]
+async fn shutdown_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut terminate = signal(SignalKind::terminate()).expect("SIGTERM handler");
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate.recv() => {}
+    }
+}
+
+#[cfg(not(unix))]
+async fn shutdown_signal() {
+    let _ = tokio::signal::ctrl_c().await;
+}
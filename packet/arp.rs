@@ -0,0 +1,119 @@
+use std::io;
+use std::net::Ipv4Addr;
+use std::time::{Duration, Instant};
+
+use pnet::datalink::{self, Channel, NetworkInterface};
+use pnet::packet::arp::{ArpHardwareTypes, ArpOperations, ArpPacket, MutableArpPacket};
+use pnet::packet::ethernet::{EtherTypes, EthernetPacket, MutableEthernetPacket};
+use pnet::packet::Packet;
+use pnet::util::MacAddr;
+use rand::Rng;
+
+// RFC 5227 2.1.1 timing
+pub const PROBE_WAIT: Duration = Duration::from_secs(1);
+pub const PROBE_NUM: u32 = 3;
+pub const PROBE_MIN: Duration = Duration::from_secs(1);
+pub const PROBE_MAX: Duration = Duration::from_secs(2);
+pub const ANNOUNCE_WAIT: Duration = Duration::from_secs(2);
+
+const ETHERNET_HEADER_LEN: usize = 14;
+const ARP_PACKET_LEN: usize = 28;
+
+// ARP request from 0.0.0.0 asking who has addr
+pub fn probe_frame(mac: MacAddr, addr: Ipv4Addr) -> Vec<u8> {
+    let mut frame = vec![0u8; ETHERNET_HEADER_LEN + ARP_PACKET_LEN];
+    {
+        let mut ethernet = MutableEthernetPacket::new(&mut frame).unwrap();
+        ethernet.set_destination(MacAddr::broadcast());
+        ethernet.set_source(mac);
+        ethernet.set_ethertype(EtherTypes::Arp);
+    }
+    let mut arp = MutableArpPacket::new(&mut frame[ETHERNET_HEADER_LEN..]).unwrap();
+    arp.set_hardware_type(ArpHardwareTypes::Ethernet);
+    arp.set_protocol_type(EtherTypes::Ipv4);
+    arp.set_hw_addr_len(6);
+    arp.set_proto_addr_len(4);
+    arp.set_operation(ArpOperations::Request);
+    arp.set_sender_hw_addr(mac);
+    arp.set_sender_proto_addr(Ipv4Addr::UNSPECIFIED);
+    arp.set_target_hw_addr(MacAddr::zero());
+    arp.set_target_proto_addr(addr);
+    frame
+}
+
+// another host owns addr if it sends ARP from it, or probes for it as well
+pub fn conflicting_sender(frame: &[u8], mac: MacAddr, addr: Ipv4Addr) -> Option<MacAddr> {
+    let ethernet = EthernetPacket::new(frame)?;
+    if ethernet.get_ethertype() != EtherTypes::Arp {
+        return None;
+    }
+    let arp = ArpPacket::new(ethernet.payload())?;
+    let sender = arp.get_sender_hw_addr();
+    if sender == mac {
+        return None;
+    }
+    let probing = arp.get_operation() == ArpOperations::Request
+        && arp.get_sender_proto_addr() == Ipv4Addr::UNSPECIFIED
+        && arp.get_target_proto_addr() == addr;
+    if arp.get_sender_proto_addr() == addr || probing {
+        return Some(sender);
+    }
+    None
+}
+
+// RFC 5227 address probe, blocks for several seconds and returns the MAC of
+// the host already using addr
+pub fn probe(
+    interface: &NetworkInterface,
+    mac: MacAddr,
+    addr: Ipv4Addr,
+) -> io::Result<Option<MacAddr>> {
+    let config = datalink::Config {
+        read_timeout: Some(Duration::from_millis(100)),
+        ..Default::default()
+    };
+    let (mut tx, mut rx) = match datalink::channel(interface, config)? {
+        Channel::Ethernet(tx, rx) => (tx, rx),
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "unsupported datalink channel",
+            ))
+        }
+    };
+    let frame = probe_frame(mac, addr);
+    let mut rng = rand::thread_rng();
+    let mut next = Instant::now() + rng.gen_range(Duration::ZERO..PROBE_WAIT);
+    let mut sent = 0;
+    loop {
+        let now = Instant::now();
+        if now >= next {
+            if sent == PROBE_NUM {
+                return Ok(None);
+            }
+            if let Some(result) = tx.send_to(&frame, None) {
+                result?;
+            }
+            sent += 1;
+            next = now
+                + if sent < PROBE_NUM {
+                    rng.gen_range(PROBE_MIN..=PROBE_MAX)
+                } else {
+                    ANNOUNCE_WAIT
+                };
+        }
+        match rx.next() {
+            Ok(frame) => {
+                if let Some(sender) = conflicting_sender(frame, mac, addr) {
+                    return Ok(Some(sender));
+                }
+            }
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+                ) => {}
+            Err(e) => return Err(e),
+        }
+    }
+}
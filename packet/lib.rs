@@ -1 +1,2 @@
+pub mod arp;
 pub mod udp;
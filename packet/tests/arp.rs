@@ -0,0 +1,68 @@
+use std::net::Ipv4Addr;
+
+use packet::arp::*;
+use pnet::packet::arp::{ArpOperations, ArpPacket, MutableArpPacket};
+use pnet::packet::ethernet::{EtherTypes, EthernetPacket, MutableEthernetPacket};
+use pnet::packet::Packet;
+use pnet::util::MacAddr;
+
+mod common;
+use common::*;
+
+const OWNER: MacAddr = MacAddr(0x02, 0, 0, 0, 0, 1);
+const ETHERNET_HEADER_LEN: usize = 14;
+
+// ARP frame of OWNER, edited by arp
+fn frame_of(arp: impl FnOnce(&mut MutableArpPacket)) -> Vec<u8> {
+    let mut frame = probe_frame(OWNER, ADDRESS);
+    arp(&mut MutableArpPacket::new(&mut frame[ETHERNET_HEADER_LEN..]).unwrap());
+    frame
+}
+
+#[test]
+fn probe_asks_who_has_the_address_from_zero() {
+    let frame = probe_frame(MAC, ADDRESS);
+    let ethernet = EthernetPacket::new(&frame).unwrap();
+    assert_eq!(ethernet.get_destination(), MacAddr::broadcast());
+    assert_eq!(ethernet.get_source(), MAC);
+    assert_eq!(ethernet.get_ethertype(), EtherTypes::Arp);
+    let arp = ArpPacket::new(ethernet.payload()).unwrap();
+    assert_eq!(arp.get_operation(), ArpOperations::Request);
+    assert_eq!(arp.get_sender_hw_addr(), MAC);
+    assert_eq!(arp.get_sender_proto_addr(), Ipv4Addr::UNSPECIFIED);
+    assert_eq!(arp.get_target_hw_addr(), MacAddr::zero());
+    assert_eq!(arp.get_target_proto_addr(), ADDRESS);
+}
+
+#[test]
+fn owner_of_the_address_conflicts() {
+    let reply = frame_of(|arp| {
+        arp.set_operation(ArpOperations::Reply);
+        arp.set_sender_proto_addr(ADDRESS);
+        arp.set_target_proto_addr(Ipv4Addr::UNSPECIFIED);
+    });
+    assert_eq!(conflicting_sender(&reply, MAC, ADDRESS), Some(OWNER));
+    // another host probing for the same address
+    let probe = probe_frame(OWNER, ADDRESS);
+    assert_eq!(conflicting_sender(&probe, MAC, ADDRESS), Some(OWNER));
+}
+
+#[test]
+fn other_traffic_does_not_conflict() {
+    // our own probe coming back
+    let ours = probe_frame(MAC, ADDRESS);
+    assert_eq!(conflicting_sender(&ours, MAC, ADDRESS), None);
+    // a probe for another address
+    let other = probe_frame(OWNER, SERVER);
+    assert_eq!(conflicting_sender(&other, MAC, ADDRESS), None);
+    // a host with another address asking for ours
+    let request = frame_of(|arp| arp.set_sender_proto_addr(SERVER));
+    assert_eq!(conflicting_sender(&request, MAC, ADDRESS), None);
+    // not ARP at all
+    let mut ipv4 = probe_frame(OWNER, ADDRESS);
+    MutableEthernetPacket::new(&mut ipv4)
+        .unwrap()
+        .set_ethertype(EtherTypes::Ipv4);
+    assert_eq!(conflicting_sender(&ipv4, MAC, ADDRESS), None);
+    assert_eq!(conflicting_sender(&[0u8; 10], MAC, ADDRESS), None);
+}
@@ -0,0 +1,101 @@
+// Builders shared by the test crates, each uses only some of them
+#![allow(dead_code)]
+
+use std::net::Ipv4Addr;
+use std::time::Instant;
+
+use packet::udp::dhcp::client::*;
+use packet::udp::dhcp::*;
+use pnet::util::MacAddr;
+
+pub const MAC: MacAddr = MacAddr(0xf6, 0x6d, 0x3f, 0xc0, 0x8a, 0x6f);
+pub const SERVER: Ipv4Addr = Ipv4Addr::new(192, 168, 1, 1);
+pub const ADDRESS: Ipv4Addr = Ipv4Addr::new(192, 168, 1, 100);
+pub const LEASE_TIME: u32 = 3600;
+
+pub fn option(tp: DHCPOPTION, value: DhcpOptionValue) -> DHCPOPTIONS {
+    DHCPOPTIONS::from_value(tp, &value).unwrap()
+}
+
+// client of MAC that binds without probing the address first
+pub fn config() -> DhcpClientConfig {
+    let mut config = DhcpClientConfig::new(MAC);
+    config.probe_address = false;
+    config
+}
+
+// answer of server to a message of the client, offering ADDRESS for
+// LEASE_TIME
+pub fn reply(to: &DHCPMessage, message_type: DHCPMessageType, server: Ipv4Addr) -> DHCPMessage {
+    let mut reply = DHCPMessage::new(message_type, MAC);
+    reply.xid = to.xid;
+    reply.yiaddr = ADDRESS;
+    reply
+        .set_option_value(
+            DHCPOPTION::ServerIdentifier,
+            DhcpOptionValue::Address(server),
+        )
+        .unwrap();
+    reply
+        .set_option_value(
+            DHCPOPTION::IPAddressLeaseTime,
+            DhcpOptionValue::Duration(LEASE_TIME),
+        )
+        .unwrap();
+    reply
+}
+
+pub fn ack_with(lease_time: u32, t1: Option<u32>, t2: Option<u32>) -> DHCPMessage {
+    let mut ack = DHCPMessage::new(DHCPMessageType::DHCPACK, MAC);
+    ack.yiaddr = ADDRESS;
+    ack.set_option_value(
+        DHCPOPTION::ServerIdentifier,
+        DhcpOptionValue::Address(SERVER),
+    )
+    .unwrap();
+    ack.set_option_value(
+        DHCPOPTION::IPAddressLeaseTime,
+        DhcpOptionValue::Duration(lease_time),
+    )
+    .unwrap();
+    if let Some(t1) = t1 {
+        ack.set_option_value(DHCPOPTION::RenewalTimeValue, DhcpOptionValue::Duration(t1))
+            .unwrap();
+    }
+    if let Some(t2) = t2 {
+        ack.set_option_value(
+            DHCPOPTION::RebindingTimeValue,
+            DhcpOptionValue::Duration(t2),
+        )
+        .unwrap();
+    }
+    ack
+}
+
+// lease of address from server acquired now, with the default T1 and T2
+pub fn lease(address: Ipv4Addr, server: Ipv4Addr, options: Vec<DHCPOPTIONS>) -> DhcpLease {
+    DhcpLease {
+        address,
+        server_identifier: server,
+        lease_time: LEASE_TIME,
+        renewal_time: LEASE_TIME / 2,
+        rebinding_time: LEASE_TIME / 8 * 7,
+        acquired: Instant::now(),
+        options,
+    }
+}
+
+// the messages among actions, in order
+pub fn sent(actions: &[DhcpAction]) -> Vec<(DHCPMessage, DhcpDestination)> {
+    actions
+        .iter()
+        .filter_map(|action| match action {
+            DhcpAction::Send(message, destination) => Some((*message.clone(), *destination)),
+            _ => None,
+        })
+        .collect()
+}
+
+pub fn last_sent(actions: &[DhcpAction]) -> DHCPMessage {
+    sent(actions).pop().expect("no message sent").0
+}
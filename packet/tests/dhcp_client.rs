@@ -0,0 +1,571 @@
+use std::net::Ipv4Addr;
+use std::time::{Duration, Instant};
+
+use packet::udp::dhcp::client::*;
+use packet::udp::dhcp::*;
+
+mod common;
+use common::*;
+
+fn secs(secs: u64) -> Duration {
+    Duration::from_secs(secs)
+}
+
+fn client() -> DhcpClient {
+    DhcpClient::new(config())
+}
+
+// DISCOVER, OFFER, REQUEST, ACK at now
+fn bound(client: &mut DhcpClient, now: Instant) -> DhcpLease {
+    let discover = last_sent(&client.start(now));
+    let offer = reply(&discover, DHCPMessageType::DHCPOFFER, SERVER);
+    let request = last_sent(&client.handle_message(offer, now));
+    let ack = reply(&request, DHCPMessageType::DHCPACK, SERVER);
+    match client.handle_message(ack, now).as_slice() {
+        [DhcpAction::Bound(lease)] => lease.clone(),
+        actions => panic!("not bound: {:?}", actions),
+    }
+}
+
+#[test]
+fn missing_t1_t2_default_to_half_and_seven_eighths() {
+    let now = Instant::now();
+    let lease = DhcpLease::from_ack(&ack_with(3600, None, None), now).unwrap();
+    assert_eq!((lease.renewal_time, lease.rebinding_time), (1800, 3150));
+    assert_eq!(lease.renew_at(), Some(now + secs(1800)));
+    assert_eq!(lease.rebind_at(), Some(now + secs(3150)));
+    assert_eq!(lease.expires_at(), Some(now + secs(3600)));
+    let lease = DhcpLease::from_ack(&ack_with(3600, Some(1000), Some(2000)), now).unwrap();
+    assert_eq!((lease.renewal_time, lease.rebinding_time), (1000, 2000));
+}
+
+#[test]
+fn out_of_order_t1_t2_are_reset_to_the_defaults() {
+    let now = Instant::now();
+    for (t1, t2) in [(3000, 2000), (1000, 4000), (4000, 5000)] {
+        let lease = DhcpLease::from_ack(&ack_with(3600, Some(t1), Some(t2)), now).unwrap();
+        assert_eq!(
+            (lease.renewal_time, lease.rebinding_time),
+            (1800, 3150),
+            "T1 {} T2 {}",
+            t1,
+            t2
+        );
+    }
+}
+
+#[test]
+fn infinite_lease_has_no_deadline() {
+    let now = Instant::now();
+    let lease = DhcpLease::from_ack(&ack_with(u32::MAX, None, None), now).unwrap();
+    assert!(lease.is_infinite());
+    assert_eq!(lease.renew_at(), None);
+    assert_eq!(lease.rebind_at(), None);
+    assert_eq!(lease.expires_at(), None);
+
+    let mut client = client();
+    let discover = last_sent(&client.start(now));
+    let offer = reply(&discover, DHCPMessageType::DHCPOFFER, SERVER);
+    let request = last_sent(&client.handle_message(offer, now));
+    let mut ack = reply(&request, DHCPMessageType::DHCPACK, SERVER);
+    ack.set_option_value(
+        DHCPOPTION::IPAddressLeaseTime,
+        DhcpOptionValue::Duration(u32::MAX),
+    )
+    .unwrap();
+    client.handle_message(ack, now);
+    assert_eq!(client.state(), DhcpState::Bound);
+    assert_eq!(client.timeout(), None);
+    assert!(client
+        .handle_timeout(now + secs(u32::MAX as u64))
+        .is_empty());
+}
+
+#[test]
+fn renews_rebinds_and_expires() {
+    let start = Instant::now();
+    let mut client = client();
+    let lease = bound(&mut client, start);
+    let (t1, t2, end) = (
+        lease.renew_at().unwrap(),
+        lease.rebind_at().unwrap(),
+        lease.expires_at().unwrap(),
+    );
+    assert_eq!(client.timeout(), Some(t1));
+    assert!(client.handle_timeout(t1 - secs(1)).is_empty());
+
+    let actions = client.handle_timeout(t1);
+    assert_eq!(client.state(), DhcpState::Renewing);
+    let (request, destination) = sent(&actions).pop().unwrap();
+    assert_eq!(request.message_type(), Some(DHCPMessageType::DHCPREQUEST));
+    assert_eq!(destination, DhcpDestination::Unicast(SERVER));
+    assert_eq!(request.ciaddr, ADDRESS);
+    assert_eq!(request.server_identifier(), None);
+
+    let actions = client.handle_timeout(t2);
+    assert_eq!(client.state(), DhcpState::Rebinding);
+    let (request, destination) = sent(&actions).pop().unwrap();
+    assert_eq!(request.message_type(), Some(DHCPMessageType::DHCPREQUEST));
+    assert_eq!(destination, DhcpDestination::Broadcast);
+    assert_eq!(request.ciaddr, ADDRESS);
+
+    let actions = client.handle_timeout(end);
+    assert!(matches!(&actions[0], DhcpAction::Expired(expired) if expired.address == ADDRESS));
+    let (discover, destination) = sent(&actions).pop().unwrap();
+    assert_eq!(discover.message_type(), Some(DHCPMessageType::DHCPDISCOVER));
+    assert_eq!(destination, DhcpDestination::Broadcast);
+    assert_eq!(client.state(), DhcpState::Selecting);
+    assert_eq!(client.lease(), None);
+}
+
+#[test]
+fn retransmissions_wait_a_minute_but_not_past_t2_or_expiry() {
+    let start = Instant::now();
+    let mut client = client();
+    let lease = bound(&mut client, start);
+    let (t2, end) = (lease.rebind_at().unwrap(), lease.expires_at().unwrap());
+    let mut now = lease.renew_at().unwrap();
+    let mut requests = 0;
+    while now < end {
+        let next = if now < t2 { t2 } else { end };
+        let state = client.state();
+        assert!(!sent(&client.handle_timeout(now)).is_empty());
+        requests += 1;
+        let deadline = client.timeout().unwrap();
+        assert!(deadline <= next, "{:?} past {:?}", deadline, next);
+        let wait = deadline - now;
+        assert!(
+            wait >= secs(DHCP_MIN_RETRANSMIT_SECS).min(next - now),
+            "{:?} waited {:?}",
+            state,
+            wait
+        );
+        now = deadline;
+    }
+    // a handful per phase instead of one per second
+    assert!(requests < 20, "{} requests", requests);
+    assert!(client
+        .handle_timeout(now)
+        .iter()
+        .any(|action| matches!(action, DhcpAction::Expired(_))));
+}
+
+const OTHER_SERVER: Ipv4Addr = Ipv4Addr::new(192, 168, 1, 2);
+const OTHER_ADDRESS: Ipv4Addr = Ipv4Addr::new(192, 168, 1, 200);
+const OFFER_WINDOW: Duration = Duration::from_secs(5);
+
+fn offer(to: &DHCPMessage, server: Ipv4Addr, address: Ipv4Addr, lease_time: u32) -> DHCPMessage {
+    let mut offer = reply(to, DHCPMessageType::DHCPOFFER, server);
+    offer.yiaddr = address;
+    offer
+        .set_option_value(
+            DHCPOPTION::IPAddressLeaseTime,
+            DhcpOptionValue::Duration(lease_time),
+        )
+        .unwrap();
+    offer
+}
+
+// a client collecting offers, that held previous before
+fn selecting(
+    policy: DhcpOfferPolicy,
+    previous: Option<Ipv4Addr>,
+    now: Instant,
+) -> (DhcpClient, DHCPMessage) {
+    let mut config = config();
+    config.offer_window = OFFER_WINDOW;
+    config.offer_policy = policy;
+    let mut client = DhcpClient::new(config);
+    if let Some(previous) = previous {
+        let ack = ack_with(LEASE_TIME, None, None);
+        let mut lease = DhcpLease::from_ack(&ack, now).unwrap();
+        lease.address = previous;
+        client.restore(lease);
+        client.release(now);
+    }
+    let discover = last_sent(&client.start(now));
+    assert_eq!(discover.message_type(), Some(DHCPMessageType::DHCPDISCOVER));
+    (client, discover)
+}
+
+// feeds offers in order and closes the window, returns the rejected
+// offers and the REQUEST
+fn select(
+    client: &mut DhcpClient,
+    offers: Vec<DHCPMessage>,
+    now: Instant,
+) -> (Vec<(Ipv4Addr, DhcpOfferRejection)>, DHCPMessage) {
+    let mut rejected = Vec::new();
+    let mut actions = Vec::new();
+    for offer in offers {
+        actions.extend(client.handle_message(offer, now));
+    }
+    assert_eq!(client.state(), DhcpState::Selecting);
+    assert_eq!(client.timeout(), Some(now + OFFER_WINDOW));
+    assert!(client
+        .handle_timeout(now + OFFER_WINDOW - secs(1))
+        .is_empty());
+    actions.extend(client.handle_timeout(now + OFFER_WINDOW));
+    for action in &actions {
+        if let DhcpAction::OfferRejected(offer, reason) = action {
+            rejected.push((offer.server_identifier().unwrap(), *reason));
+        }
+    }
+    assert_eq!(client.state(), DhcpState::Requesting);
+    (rejected, last_sent(&actions))
+}
+
+fn assert_requests(request: &DHCPMessage, server: Ipv4Addr, address: Ipv4Addr) {
+    assert_eq!(request.message_type(), Some(DHCPMessageType::DHCPREQUEST));
+    assert_eq!(request.server_identifier(), Some(server));
+    assert_eq!(request.requested_ip_address(), Some(address));
+}
+
+#[test]
+fn offers_of_rejected_servers_are_never_taken() {
+    let now = Instant::now();
+    let policy = DhcpOfferPolicy {
+        rejected_servers: vec![OTHER_SERVER],
+        ..Default::default()
+    };
+    let (mut client, discover) = selecting(policy, None, now);
+    let offers = vec![
+        offer(&discover, OTHER_SERVER, OTHER_ADDRESS, LEASE_TIME * 2),
+        offer(&discover, SERVER, ADDRESS, LEASE_TIME),
+    ];
+    let (rejected, request) = select(&mut client, offers, now);
+    assert_eq!(
+        rejected,
+        vec![(OTHER_SERVER, DhcpOfferRejection::RejectedServer)]
+    );
+    assert_requests(&request, SERVER, ADDRESS);
+}
+
+#[test]
+fn preferred_server_wins() {
+    let now = Instant::now();
+    let policy = DhcpOfferPolicy {
+        preferred_servers: vec![OTHER_SERVER],
+        prefer_longest_lease: true,
+        ..Default::default()
+    };
+    let (mut client, discover) = selecting(policy, None, now);
+    let offers = vec![
+        offer(&discover, SERVER, ADDRESS, LEASE_TIME * 2),
+        offer(&discover, OTHER_SERVER, OTHER_ADDRESS, LEASE_TIME),
+    ];
+    let (rejected, request) = select(&mut client, offers, now);
+    assert_eq!(rejected, vec![(SERVER, DhcpOfferRejection::NotSelected)]);
+    assert_requests(&request, OTHER_SERVER, OTHER_ADDRESS);
+}
+
+#[test]
+fn offer_of_the_previous_address_wins() {
+    let now = Instant::now();
+    let policy = DhcpOfferPolicy {
+        prefer_previous_address: true,
+        prefer_longest_lease: true,
+        ..Default::default()
+    };
+    let (mut client, discover) = selecting(policy, Some(OTHER_ADDRESS), now);
+    let offers = vec![
+        offer(&discover, SERVER, ADDRESS, LEASE_TIME * 2),
+        offer(&discover, OTHER_SERVER, OTHER_ADDRESS, LEASE_TIME),
+    ];
+    let (rejected, request) = select(&mut client, offers, now);
+    assert_eq!(rejected, vec![(SERVER, DhcpOfferRejection::NotSelected)]);
+    assert_requests(&request, OTHER_SERVER, OTHER_ADDRESS);
+}
+
+#[test]
+fn longest_lease_wins() {
+    let now = Instant::now();
+    let policy = DhcpOfferPolicy {
+        prefer_longest_lease: true,
+        ..Default::default()
+    };
+    let (mut client, discover) = selecting(policy, None, now);
+    let offers = vec![
+        offer(&discover, SERVER, ADDRESS, LEASE_TIME),
+        offer(&discover, OTHER_SERVER, OTHER_ADDRESS, LEASE_TIME * 2),
+    ];
+    let (rejected, request) = select(&mut client, offers, now);
+    assert_eq!(rejected, vec![(SERVER, DhcpOfferRejection::NotSelected)]);
+    assert_requests(&request, OTHER_SERVER, OTHER_ADDRESS);
+}
+
+#[test]
+fn tie_keeps_the_earlier_offer() {
+    let now = Instant::now();
+    let policy = DhcpOfferPolicy {
+        prefer_longest_lease: true,
+        ..Default::default()
+    };
+    let (mut client, discover) = selecting(policy, None, now);
+    let offers = vec![
+        offer(&discover, SERVER, ADDRESS, LEASE_TIME),
+        offer(&discover, OTHER_SERVER, OTHER_ADDRESS, LEASE_TIME),
+    ];
+    let (rejected, request) = select(&mut client, offers, now);
+    assert_eq!(
+        rejected,
+        vec![(OTHER_SERVER, DhcpOfferRejection::NotSelected)]
+    );
+    assert_requests(&request, SERVER, ADDRESS);
+}
+
+fn with_retransmits(max_retransmits: u32) -> DhcpClient {
+    let mut config = config();
+    config.max_retransmits = max_retransmits;
+    DhcpClient::new(config)
+}
+
+// lets every transmission of the exchange time out, checking the backoff,
+// and returns the actions of the last timeout
+fn time_out(client: &mut DhcpClient, transmissions: u32, start: Instant) -> Vec<DhcpAction> {
+    let mut now = start;
+    let mut actions = Vec::new();
+    for attempt in 0..transmissions {
+        // 4, 8, 16 ... 64 seconds, each randomized by a second
+        let backoff = (DHCP_INITIAL_BACKOFF_SECS << attempt).min(DHCP_MAX_BACKOFF_SECS);
+        let deadline = client.timeout().unwrap();
+        let wait = deadline - now;
+        assert!(
+            wait >= secs(backoff - 1) && wait <= secs(backoff + 1),
+            "attempt {} waited {:?}",
+            attempt,
+            wait
+        );
+        now = deadline;
+        actions = client.handle_timeout(now);
+        if attempt + 1 < transmissions {
+            let retransmitted = last_sent(&actions);
+            assert_eq!(retransmitted.secs as u64, (now - start).as_secs());
+        }
+    }
+    actions
+}
+
+#[test]
+fn discover_times_out_after_the_configured_retransmissions() {
+    let start = Instant::now();
+    let mut client = with_retransmits(6);
+    let discover = last_sent(&client.start(start));
+    assert_eq!(discover.message_type(), Some(DHCPMessageType::DHCPDISCOVER));
+    let actions = time_out(&mut client, 7, start);
+    assert_eq!(actions, vec![DhcpAction::TimedOut(DhcpState::Selecting)]);
+    assert_eq!(client.timeout(), None);
+}
+
+#[test]
+fn inform_without_retransmissions_times_out_at_once() {
+    let start = Instant::now();
+    let mut client = with_retransmits(0);
+    client.start_inform(ADDRESS, start);
+    let actions = time_out(&mut client, 1, start);
+    assert_eq!(actions, vec![DhcpAction::TimedOut(DhcpState::Informing)]);
+}
+
+#[test]
+fn unanswered_request_starts_over() {
+    let start = Instant::now();
+    let mut client = with_retransmits(1);
+    let discover = last_sent(&client.start(start));
+    let offer = reply(&discover, DHCPMessageType::DHCPOFFER, SERVER);
+    let request = last_sent(&client.handle_message(offer, start));
+    assert_eq!(request.message_type(), Some(DHCPMessageType::DHCPREQUEST));
+    let actions = time_out(&mut client, 2, start);
+    let discover = last_sent(&actions);
+    assert_eq!(discover.message_type(), Some(DHCPMessageType::DHCPDISCOVER));
+    assert_eq!(client.state(), DhcpState::Selecting);
+}
+
+fn nak(to: &DHCPMessage) -> DHCPMessage {
+    let mut nak = DHCPMessage::new(DHCPMessageType::DHCPNAK, MAC);
+    nak.xid = to.xid;
+    nak.set_option_value(
+        DHCPOPTION::ServerIdentifier,
+        DhcpOptionValue::Address(SERVER),
+    )
+    .unwrap();
+    nak.set_option_value(
+        DHCPOPTION::Message,
+        DhcpOptionValue::String("wrong network".to_string()),
+    )
+    .unwrap();
+    nak
+}
+
+// the NAK drops the lease and reports the server's text, DISCOVER follows
+// only after the restart delay
+fn assert_naked(
+    client: &mut DhcpClient,
+    request: &DHCPMessage,
+    lease: Option<DhcpLease>,
+    now: Instant,
+) {
+    let actions = client.handle_message(nak(request), now);
+    assert_eq!(
+        actions,
+        vec![DhcpAction::Nak {
+            message: Some("wrong network".to_string()),
+            lease,
+        }]
+    );
+    assert_eq!(client.state(), DhcpState::Init);
+    assert_eq!(client.lease(), None);
+    let restart = now + secs(DHCP_RESTART_DELAY_SECS);
+    assert_eq!(client.timeout(), Some(restart));
+    assert!(client.handle_timeout(restart - secs(1)).is_empty());
+    let discover = last_sent(&client.handle_timeout(restart));
+    assert_eq!(discover.message_type(), Some(DHCPMessageType::DHCPDISCOVER));
+    assert_eq!(client.state(), DhcpState::Selecting);
+}
+
+#[test]
+fn nak_while_requesting_restarts_after_a_delay() {
+    let now = Instant::now();
+    let mut client = client();
+    let discover = last_sent(&client.start(now));
+    let offer = reply(&discover, DHCPMessageType::DHCPOFFER, SERVER);
+    let request = last_sent(&client.handle_message(offer, now));
+    assert_eq!(client.state(), DhcpState::Requesting);
+    assert_naked(&mut client, &request, None, now);
+}
+
+#[test]
+fn nak_while_renewing_drops_the_lease() {
+    let start = Instant::now();
+    let mut client = client();
+    let lease = bound(&mut client, start);
+    let t1 = lease.renew_at().unwrap();
+    let request = last_sent(&client.handle_timeout(t1));
+    assert_eq!(client.state(), DhcpState::Renewing);
+    assert_naked(&mut client, &request, Some(lease), t1);
+}
+
+#[test]
+fn nak_while_rebooting_restarts_after_a_delay() {
+    let now = Instant::now();
+    let mut config = DhcpClientConfig::new(MAC);
+    config.requested_address = Some(ADDRESS);
+    let mut client = DhcpClient::new(config);
+    let request = last_sent(&client.start(now));
+    assert_eq!(client.state(), DhcpState::Rebooting);
+    assert_eq!(request.requested_ip_address(), Some(ADDRESS));
+    assert_naked(&mut client, &request, None, now);
+}
+
+// a probing client holding the ACK of REQUEST until handle_probe
+fn probing(now: Instant) -> (DhcpClient, DHCPMessage) {
+    let mut config = config();
+    config.probe_address = true;
+    let mut client = DhcpClient::new(config);
+    let discover = last_sent(&client.start(now));
+    let offer = reply(&discover, DHCPMessageType::DHCPOFFER, SERVER);
+    let request = last_sent(&client.handle_message(offer, now));
+    let ack = reply(&request, DHCPMessageType::DHCPACK, SERVER);
+    assert_eq!(
+        client.handle_message(ack, now),
+        vec![DhcpAction::Probe(ADDRESS)]
+    );
+    assert_eq!(client.state(), DhcpState::Requesting);
+    assert_eq!(client.lease(), None);
+    (client, request)
+}
+
+#[test]
+fn unused_address_is_bound_after_the_probe() {
+    let now = Instant::now();
+    let (mut client, _) = probing(now);
+    let actions = client.handle_probe(false, now);
+    assert!(matches!(actions.as_slice(), [DhcpAction::Bound(lease)] if lease.address == ADDRESS));
+    assert_eq!(client.state(), DhcpState::Bound);
+}
+
+#[test]
+fn address_in_use_is_declined() {
+    let now = Instant::now();
+    let (mut client, _) = probing(now);
+    let actions = client.handle_probe(true, now);
+    assert!(matches!(&actions[1], DhcpAction::Declined(lease) if lease.address == ADDRESS));
+    let (decline, destination) = sent(&actions).pop().unwrap();
+    assert_eq!(decline.message_type(), Some(DHCPMessageType::DHCPDECLINE));
+    assert_eq!(destination, DhcpDestination::Broadcast);
+    assert_eq!(decline.requested_ip_address(), Some(ADDRESS));
+    assert_eq!(decline.server_identifier(), Some(SERVER));
+    assert_eq!(decline.option(DHCPOPTION::ParameterRequestList), None);
+    assert_eq!(client.state(), DhcpState::Init);
+    assert_eq!(client.lease(), None);
+
+    let restart = now + secs(DHCP_RESTART_DELAY_SECS);
+    assert_eq!(client.timeout(), Some(restart));
+    assert!(client.handle_timeout(restart - secs(1)).is_empty());
+    let discover = last_sent(&client.handle_timeout(restart));
+    assert_eq!(discover.message_type(), Some(DHCPMessageType::DHCPDISCOVER));
+}
+
+#[test]
+fn nak_while_probing_discards_the_address() {
+    let now = Instant::now();
+    let (mut client, request) = probing(now);
+    client.handle_message(nak(&request), now);
+    assert_eq!(client.state(), DhcpState::Init);
+    assert!(client.handle_probe(false, now).is_empty());
+    assert_eq!(client.state(), DhcpState::Init);
+    assert_eq!(client.lease(), None);
+}
+
+#[test]
+fn replies_to_other_exchanges_are_dropped() {
+    let now = Instant::now();
+    let mut client = client();
+    let discover = last_sent(&client.start(now));
+    let offer = reply(&discover, DHCPMessageType::DHCPOFFER, SERVER);
+
+    let mut foreign_xid = offer.clone();
+    foreign_xid.xid = discover.xid.wrapping_add(1);
+    let mut foreign_chaddr = offer.clone();
+    foreign_chaddr.chaddr[5] ^= 0xFF;
+    let mut request = offer.clone();
+    request.op = DHCPOP::BOOTREQUEST;
+    for message in [foreign_xid, foreign_chaddr, request] {
+        assert!(!client.accepts(&message));
+        assert!(client.handle_message(message, now).is_empty());
+        assert_eq!(client.state(), DhcpState::Selecting);
+    }
+
+    assert!(client.accepts(&offer));
+    client.handle_message(offer, now);
+    assert_eq!(client.state(), DhcpState::Requesting);
+}
+
+#[test]
+fn every_exchange_has_a_new_xid() {
+    let start = Instant::now();
+    let mut client = client();
+    let discover = last_sent(&client.start(start));
+    let offer = reply(&discover, DHCPMessageType::DHCPOFFER, SERVER);
+    let request = last_sent(&client.handle_message(offer, start));
+    // the REQUEST continues the exchange of the DISCOVER
+    assert_eq!(request.xid, discover.xid);
+    let ack = reply(&request, DHCPMessageType::DHCPACK, SERVER);
+    let lease = match client.handle_message(ack, start).as_slice() {
+        [DhcpAction::Bound(lease)] => lease.clone(),
+        actions => panic!("not bound: {:?}", actions),
+    };
+
+    let t1 = lease.renew_at().unwrap();
+    let renew = last_sent(&client.handle_timeout(t1));
+    assert_ne!(renew.xid, discover.xid);
+
+    client.handle_message(nak(&renew), t1);
+    let restart = t1 + secs(DHCP_RESTART_DELAY_SECS);
+    let rediscover = last_sent(&client.handle_timeout(restart));
+    assert_eq!(
+        rediscover.message_type(),
+        Some(DHCPMessageType::DHCPDISCOVER)
+    );
+    assert_ne!(rediscover.xid, renew.xid);
+    assert_ne!(rediscover.xid, discover.xid);
+}
@@ -0,0 +1,86 @@
+use std::collections::BTreeMap;
+use std::fs;
+
+use packet::udp::dhcp::duid::*;
+use packet::udp::dhcp::*;
+use pnet::util::MacAddr;
+
+mod common;
+use common::*;
+
+#[test]
+fn encodes_every_duid_type() {
+    let llt = DhcpDuid::Llt {
+        hardware_type: 1,
+        time: 0x2A2B2C2D,
+        address: MAC,
+    };
+    assert_eq!(
+        llt.to_bytes(),
+        [0, 1, 0, 1, 0x2A, 0x2B, 0x2C, 0x2D, 0xf6, 0x6d, 0x3f, 0xc0, 0x8a, 0x6f]
+    );
+    let en = DhcpDuid::En {
+        enterprise_number: 32473,
+        identifier: vec![0xAA, 0xBB],
+    };
+    assert_eq!(en.to_bytes(), [0, 2, 0, 0, 0x7E, 0xD9, 0xAA, 0xBB]);
+    assert_eq!(
+        DhcpDuid::ll(MAC).to_bytes(),
+        [0, 3, 0, 1, 0xf6, 0x6d, 0x3f, 0xc0, 0x8a, 0x6f]
+    );
+    let uuid = DhcpDuid::Uuid { uuid: [7; 16] };
+    let mut bytes = vec![0, 4];
+    bytes.extend([7; 16]);
+    assert_eq!(uuid.to_bytes(), bytes);
+}
+
+#[test]
+fn client_identifier_is_type_255_with_iaid_and_duid() {
+    let duid = DhcpDuid::ll(MAC);
+    let value = client_identifier(0x01020304, &duid);
+    let mut id = vec![1, 2, 3, 4];
+    id.extend(duid.to_bytes());
+    assert_eq!(value, DhcpOptionValue::ClientIdentifier { tp: 255, id });
+    let option = DHCPOPTIONS::from_value(DHCPOPTION::ClientIdentifier, &value).unwrap();
+    assert_eq!(option.va[0], 255);
+    assert_eq!(option.value().unwrap(), value);
+}
+
+#[test]
+fn keeps_the_stored_duid_until_the_type_changes() {
+    let dir = std::env::temp_dir().join(format!("inode-duid-{}", std::process::id()));
+    let path = dir.join("duid.json");
+    let llt = DhcpDuid::load_or_generate(&path, &DhcpDuidType::Llt, MAC).unwrap();
+    let other = MacAddr(2, 0, 0, 0, 0, 1);
+    assert_eq!(
+        DhcpDuid::load_or_generate(&path, &DhcpDuidType::Llt, other).unwrap(),
+        llt
+    );
+    let en = DhcpDuidType::En {
+        enterprise_number: 32473,
+    };
+    let generated = DhcpDuid::load_or_generate(&path, &en, MAC).unwrap();
+    assert!(generated.is(&en));
+    assert_eq!(DhcpDuid::load(&path).unwrap(), generated);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn parses_the_config_section() {
+    let config: DhcpDuidConfig =
+        serde_json::from_str(r#"{"type": "en", "enterprise-number": 32473, "iaid": {"eth0": 1}}"#)
+            .unwrap();
+    assert_eq!(
+        config,
+        DhcpDuidConfig {
+            tp: DhcpDuidType::En {
+                enterprise_number: 32473
+            },
+            file: DHCP_DUID_FILE.into(),
+            iaid: BTreeMap::from([("eth0".to_string(), 1)]),
+        }
+    );
+    assert_eq!(config.iaid("eth0"), 1);
+    assert_eq!(config.iaid("eth1"), iaid("eth1"));
+    assert_ne!(iaid("eth1"), iaid("eth2"));
+}
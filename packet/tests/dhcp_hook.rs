@@ -0,0 +1,136 @@
+use std::net::Ipv4Addr;
+
+use packet::udp::dhcp::client::DhcpLease;
+use packet::udp::dhcp::hook::*;
+use packet::udp::dhcp::*;
+
+mod common;
+use common::option;
+
+fn lease(address: Ipv4Addr) -> DhcpLease {
+    let server = Ipv4Addr::new(10, 0, 0, 1);
+    let options = vec![
+        option(
+            DHCPOPTION::SubnetMask,
+            DhcpOptionValue::Address(Ipv4Addr::new(255, 255, 255, 0)),
+        ),
+        option(
+            DHCPOPTION::BroadcastAddressOption,
+            DhcpOptionValue::Address(Ipv4Addr::new(10, 0, 0, 255)),
+        ),
+        option(
+            DHCPOPTION::RouterOption,
+            DhcpOptionValue::Addresses(vec![server, Ipv4Addr::new(10, 0, 0, 2)]),
+        ),
+        option(
+            DHCPOPTION::DomainNameServerOption,
+            DhcpOptionValue::Addresses(vec![
+                Ipv4Addr::new(10, 0, 0, 53),
+                Ipv4Addr::new(10, 0, 0, 54),
+            ]),
+        ),
+        option(
+            DHCPOPTION::DomainName,
+            DhcpOptionValue::String("corp.example".to_string()),
+        ),
+        option(DHCPOPTION::InterfaceMTUOption, DhcpOptionValue::U16(1400)),
+        option(
+            DHCPOPTION::ClasslessStaticRoute,
+            DhcpOptionValue::ClasslessRoutes(vec![(
+                Ipv4Addr::new(192, 168, 0, 0),
+                16,
+                Ipv4Addr::new(10, 0, 0, 2),
+            )]),
+        ),
+    ];
+    common::lease(address, server, options)
+}
+
+fn get<'a>(env: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    env.iter()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.as_str())
+}
+
+#[test]
+fn names_and_formats_the_lease_variables() {
+    let env = lease_env("new", &lease(Ipv4Addr::new(10, 0, 0, 10)));
+    let expected = [
+        ("new_ip_address", "10.0.0.10"),
+        ("new_dhcp_server_identifier", "10.0.0.1"),
+        ("new_dhcp_lease_time", "3600"),
+        ("new_dhcp_renewal_time", "1800"),
+        ("new_dhcp_rebinding_time", "3150"),
+        ("new_subnet_mask", "255.255.255.0"),
+        ("new_network_number", "10.0.0.0"),
+        ("new_broadcast_address", "10.0.0.255"),
+        ("new_routers", "10.0.0.1 10.0.0.2"),
+        ("new_domain_name_servers", "10.0.0.53 10.0.0.54"),
+        ("new_domain_name", "corp.example"),
+        ("new_interface_mtu", "1400"),
+        // prefix length, the significant octets of the destination, router
+        ("new_rfc3442_classless_static_routes", "16 192 168 10 0 0 2"),
+    ];
+    for (name, value) in expected {
+        assert_eq!(get(&env, name), Some(value), "{}", name);
+    }
+    assert_eq!(env.len(), expected.len());
+}
+
+#[test]
+fn leaves_out_what_the_lease_does_not_have() {
+    let mut lease = lease(Ipv4Addr::new(10, 0, 0, 10));
+    lease.options.clear();
+    let env = lease_env("old", &lease);
+    let names: Vec<&str> = env.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(
+        names,
+        [
+            "old_ip_address",
+            "old_dhcp_server_identifier",
+            "old_dhcp_lease_time",
+            "old_dhcp_renewal_time",
+            "old_dhcp_rebinding_time",
+        ]
+    );
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn passes_old_and_new_leases_to_the_hook() {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = std::env::temp_dir().join(format!("inode-hook-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let script = dir.join("hook");
+    let output = dir.join("env");
+    fs::write(&script, format!("#!/bin/sh\nenv > {}\n", output.display())).unwrap();
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+
+    let old = lease(Ipv4Addr::new(10, 0, 0, 10));
+    let new = lease(Ipv4Addr::new(10, 0, 0, 11));
+    let status = run_hook(
+        &script,
+        DhcpHookReason::Renew,
+        "eth0",
+        Some(&old),
+        Some(&new),
+    )
+    .await
+    .unwrap();
+    assert!(status.success());
+
+    let env = fs::read_to_string(&output).unwrap();
+    let lines: Vec<&str> = env.lines().collect();
+    for line in [
+        "reason=RENEW",
+        "interface=eth0",
+        "old_ip_address=10.0.0.10",
+        "new_ip_address=10.0.0.11",
+        "new_routers=10.0.0.1 10.0.0.2",
+    ] {
+        assert!(lines.contains(&line), "{}", line);
+    }
+    fs::remove_dir_all(dir).unwrap();
+}
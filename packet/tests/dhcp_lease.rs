@@ -0,0 +1,137 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use packet::udp::dhcp::client::*;
+use packet::udp::dhcp::lease::*;
+use packet::udp::dhcp::transport::*;
+use packet::udp::dhcp::*;
+
+mod common;
+use common::*;
+
+// a lease file in a directory of its own
+fn lease_file(name: &str) -> (PathBuf, PathBuf) {
+    let dir = std::env::temp_dir().join(format!("inode-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    (lease_path(&dir, "eth0"), dir)
+}
+
+// record of a lease acquired age ago
+fn record(age: Duration) -> DhcpLeaseRecord {
+    let options = vec![option(
+        DHCPOPTION::RouterOption,
+        DhcpOptionValue::Addresses(vec![SERVER]),
+    )];
+    let mut record = DhcpLeaseRecord::new("eth0", MAC, &lease(ADDRESS, SERVER, options));
+    let acquired = SystemTime::now() - age;
+    record.acquired = acquired.duration_since(UNIX_EPOCH).unwrap().as_secs();
+    record
+}
+
+#[test]
+fn saves_and_loads_a_record() {
+    let (path, dir) = lease_file("lease-save");
+    let mut saved = record(Duration::ZERO);
+    saved.mtu = Some(1500);
+    saved.save(&path).unwrap();
+    assert_eq!(DhcpLeaseRecord::load(&path).unwrap(), saved);
+    DhcpLeaseRecord::remove(&path).unwrap();
+    assert!(!path.exists());
+    // nothing left to remove is fine
+    DhcpLeaseRecord::remove(&path).unwrap();
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn restored_lease_keeps_its_deadlines() {
+    let record = record(Duration::from_secs(1000));
+    assert!(!record.is_expired());
+    let lease = record.to_lease();
+    assert_eq!(lease.address, ADDRESS);
+    assert_eq!(lease.server_identifier, SERVER);
+    assert_eq!(lease.lease_time, LEASE_TIME - 1000);
+    assert_eq!(lease.renewal_time, LEASE_TIME / 2 - 1000);
+    assert_eq!(lease.rebinding_time, LEASE_TIME / 8 * 7 - 1000);
+    assert_eq!(lease.parameters().routers(), vec![SERVER]);
+
+    // past T1 the client renews at once
+    let lease = self::record(Duration::from_secs(2000)).to_lease();
+    assert_eq!(lease.renewal_time, 0);
+    assert_eq!(lease.rebinding_time, LEASE_TIME / 8 * 7 - 2000);
+}
+
+#[test]
+fn lease_expires_after_its_lease_time() {
+    assert!(record(Duration::from_secs(LEASE_TIME as u64)).is_expired());
+    let mut infinite = record(Duration::from_secs(LEASE_TIME as u64 * 10));
+    infinite.lease_time = u32::MAX;
+    assert!(!infinite.is_expired());
+    assert_eq!(infinite.to_lease().lease_time, u32::MAX);
+}
+
+// a client started over the memory transport with a lease file
+fn rebooting(
+    path: &Path,
+) -> (
+    DhcpMemoryServer,
+    tokio::sync::broadcast::Receiver<DhcpEvent>,
+) {
+    let (transport, server) = DhcpMemoryTransport::pair(None);
+    let mut config = config();
+    config.lease_file = Some(path.to_path_buf());
+    let mut client = DhcpClient::new(config);
+    let events = client.subscribe();
+    tokio::spawn(async move { client.run(&transport, std::future::pending::<()>()).await });
+    (server, events)
+}
+
+async fn receive(server: &mut DhcpMemoryServer) -> DHCPMessage {
+    let (datagram, destination) = server.recv().await.unwrap();
+    assert_eq!(destination, DhcpDestination::Broadcast);
+    DHCPMessage::try_from(&datagram[..]).unwrap()
+}
+
+#[tokio::test]
+async fn reboots_into_the_lease_of_the_file() {
+    let (path, dir) = lease_file("lease-reboot");
+    record(Duration::from_secs(100)).save(&path).unwrap();
+    let (mut server, mut events) = rebooting(&path);
+
+    let request = receive(&mut server).await;
+    assert_eq!(request.message_type(), Some(DHCPMessageType::DHCPREQUEST));
+    assert_eq!(request.requested_ip_address(), Some(ADDRESS));
+    assert_eq!(request.server_identifier(), None);
+    let ack = reply(&request, DHCPMessageType::DHCPACK, SERVER);
+    server.send(SERVER, &ack.to_bytes()).unwrap();
+
+    assert!(matches!(
+        events.recv().await.unwrap(),
+        DhcpEvent::Bound(lease) if lease.address == ADDRESS
+    ));
+    // saved again with the new acquisition time
+    assert!(DhcpLeaseRecord::load(&path).unwrap().age() < Duration::from_secs(100));
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn nak_of_the_rebooted_lease_removes_the_file() {
+    let (path, dir) = lease_file("lease-nak");
+    record(Duration::from_secs(100)).save(&path).unwrap();
+    let (mut server, mut events) = rebooting(&path);
+
+    let request = receive(&mut server).await;
+    assert_eq!(request.requested_ip_address(), Some(ADDRESS));
+    let nak = DHCPMessage {
+        xid: request.xid,
+        ..DHCPMessage::new(DHCPMessageType::DHCPNAK, MAC)
+    };
+    server.send(SERVER, &nak.to_bytes()).unwrap();
+
+    assert!(matches!(
+        events.recv().await.unwrap(),
+        DhcpEvent::Nak { lease: Some(lease), .. } if lease.address == ADDRESS
+    ));
+    assert!(!path.exists());
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,147 @@
+use packet::udp::dhcp::*;
+
+mod common;
+use common::*;
+
+fn offer_with(option: DHCPOPTIONS) -> DHCPMessage {
+    let mut message = DHCPMessage::new(DHCPMessageType::DHCPOFFER, MAC);
+    message.set_option(option);
+    message
+}
+
+// (code, len) of every option instance in the main options field
+fn instances(bytes: &[u8]) -> Vec<(u8, u8)> {
+    let mut instances = Vec::new();
+    let mut pos = DHCP_HEADER_LEN + 4;
+    while bytes[pos] != 0xff {
+        instances.push((bytes[pos], bytes[pos + 1]));
+        pos += 2 + bytes[pos + 1] as usize;
+    }
+    instances
+}
+
+fn round_trip(len: usize) -> Vec<(u8, u8)> {
+    let va: Vec<u8> = (0..len).map(|i| i as u8).collect();
+    let message = offer_with(DHCPOPTIONS::new(DHCPOPTION::VendorSpecificInformation, va));
+    let bytes = message.to_bytes_with_max_size(1500);
+    let decoded = DHCPMessage::try_from(&bytes[..]).unwrap();
+    assert_eq!(decoded, message);
+    instances(&bytes)
+        .into_iter()
+        .filter(|&(code, _)| code == 0x2B)
+        .collect()
+}
+
+#[test]
+fn empty_value_is_a_single_instance() {
+    assert_eq!(round_trip(0), vec![(0x2B, 0)]);
+}
+
+#[test]
+fn value_of_255_bytes_is_not_split() {
+    assert_eq!(round_trip(255), vec![(0x2B, 255)]);
+}
+
+#[test]
+fn value_of_256_bytes_is_split_in_two() {
+    assert_eq!(round_trip(256), vec![(0x2B, 255), (0x2B, 1)]);
+}
+
+#[test]
+fn value_of_510_bytes_fills_two_instances() {
+    assert_eq!(round_trip(510), vec![(0x2B, 255), (0x2B, 255)]);
+}
+
+#[test]
+fn value_of_511_bytes_is_split_in_three() {
+    assert_eq!(round_trip(511), vec![(0x2B, 255), (0x2B, 255), (0x2B, 1)]);
+}
+
+#[test]
+fn repeated_instances_are_concatenated() {
+    let mut bytes = DHCPMessage::new(DHCPMessageType::DHCPACK, MAC)
+        .to_bytes()
+        .to_vec();
+    bytes.pop();
+    bytes.extend_from_slice(&[0x77, 3, 1, b'a', 0]);
+    bytes.extend_from_slice(&[0x03, 4, 10, 0, 0, 1]);
+    bytes.extend_from_slice(&[0x77, 4, 1, b'b', 0xc0, 0]);
+    bytes.push(0xff);
+    let message = DHCPMessage::try_from(&bytes[..]).unwrap();
+    let search = message.option(DHCPOPTION::DomainSearch).unwrap();
+    assert_eq!(search.len, 7);
+    assert_eq!(search.va, vec![1, b'a', 0, 1, b'b', 0xc0, 0]);
+    assert_eq!(message.domain_search(), vec!["a", "b.a"]);
+    assert_eq!(
+        message.routers(),
+        vec!["10.0.0.1".parse::<std::net::Ipv4Addr>().unwrap()]
+    );
+}
+
+#[test]
+fn looping_search_pointer_is_rejected() {
+    let mut bytes = DHCPMessage::new(DHCPMessageType::DHCPACK, MAC)
+        .to_bytes()
+        .to_vec();
+    bytes.pop();
+    bytes.extend_from_slice(&[0x77, 4, 1, b'a', 0xc0, 0]);
+    bytes.push(0xff);
+    let message = DHCPMessage::try_from(&bytes[..]).unwrap();
+    assert!(message.domain_search().is_empty());
+}
+
+#[test]
+fn chained_search_pointers_are_followed() {
+    let mut message = DHCPMessage::new(DHCPMessageType::DHCPACK, MAC);
+    // "a", "b.a" pointing at "a", "c.b.a" pointing at "b.a"
+    message.set_option(DHCPOPTIONS::new(
+        DHCPOPTION::DomainSearch,
+        vec![1, b'a', 0, 1, b'b', 0xc0, 0, 1, b'c', 0xc0, 3],
+    ));
+    assert_eq!(message.domain_search(), vec!["a", "b.a", "c.b.a"]);
+}
+
+#[test]
+fn split_value_spills_into_overloaded_fields() {
+    let domains: Vec<String> = (0..12)
+        .map(|i| format!("host{}.campus.example.edu", i))
+        .collect();
+    let mut message = offer_with(
+        DHCPOPTIONS::from_value(
+            DHCPOPTION::DomainSearch,
+            &DhcpOptionValue::DomainList(domains.clone()),
+        )
+        .unwrap(),
+    );
+    message
+        .set_option_value(
+            DHCPOPTION::ClasslessStaticRoute,
+            DhcpOptionValue::ClasslessRoutes(vec![(
+                "10.0.0.0".parse().unwrap(),
+                8,
+                "192.168.1.1".parse().unwrap(),
+            )]),
+        )
+        .unwrap();
+    let bytes = message.to_bytes();
+    assert!(bytes.len() <= DHCP_MIN_MESSAGE_SIZE - DHCP_IP_UDP_HEADER_LEN);
+    assert_eq!(instances(&bytes)[0], (0x34, 1));
+    let decoded = DHCPMessage::try_from(&bytes[..]).unwrap();
+    assert_eq!(decoded, message);
+    assert_eq!(decoded.domain_search(), domains);
+}
+
+#[test]
+fn truncated_fragment_is_an_overrun() {
+    let mut bytes = offer_with(DHCPOPTIONS::new(
+        DHCPOPTION::VendorSpecificInformation,
+        vec![7; 300],
+    ))
+    .to_bytes_with_max_size(1500)
+    .to_vec();
+    bytes.truncate(bytes.len() - 10);
+    assert_eq!(
+        DHCPMessage::try_from(&bytes[..]),
+        Err(DHCPParseError::OptionOverrun(0x2B))
+    );
+}
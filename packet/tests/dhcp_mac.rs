@@ -0,0 +1,41 @@
+use packet::udp::dhcp::client::*;
+use packet::udp::dhcp::*;
+
+mod common;
+use common::*;
+
+#[test]
+fn parses_colon_and_dash_separated_addresses() {
+    assert_eq!(parse_mac("f6:6d:3f:c0:8a:6f").unwrap(), MAC);
+    assert_eq!(parse_mac("F6-6D-3F-C0-8A-6F").unwrap(), MAC);
+}
+
+#[test]
+fn rejects_malformed_and_non_unicast_addresses() {
+    for mac in [
+        "",
+        "f6:6d:3f:c0:8a",
+        "f6:6d:3f:c0:8a:6f:00",
+        "f6:6d:3f:c0:8a:zz",
+        "00:00:00:00:00:00",
+        "ff:ff:ff:ff:ff:ff",
+        "01:00:5e:00:00:01",
+    ] {
+        assert!(parse_mac(mac).is_err(), "{} accepted", mac);
+    }
+}
+
+#[test]
+fn chaddr_and_client_identifier_follow_the_mac() {
+    let config = DhcpClientConfig::new(MAC);
+    assert_eq!(
+        config.client_identifier,
+        DhcpOptionValue::ClientIdentifier {
+            tp: DHCPHType::ETHERNET as u8,
+            id: MAC.octets().to_vec(),
+        }
+    );
+    let message = DHCPMessage::new(DHCPMessageType::DHCPDISCOVER, MAC);
+    assert_eq!(message.chaddr[..6], MAC.octets());
+    assert!(message.chaddr[6..].iter().all(|&octet| octet == 0));
+}
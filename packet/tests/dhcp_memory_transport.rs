@@ -0,0 +1,102 @@
+use std::net::Ipv4Addr;
+
+use packet::udp::dhcp::client::*;
+use packet::udp::dhcp::transport::*;
+use packet::udp::dhcp::*;
+
+mod common;
+use common::*;
+
+// reply of SERVER with a router, an INFORM is answered without a lease
+fn routed_reply(to: &DHCPMessage, message_type: DHCPMessageType) -> DHCPMessage {
+    let mut reply = reply(to, message_type, SERVER);
+    if message_type == DHCPMessageType::DHCPACK && !to.ciaddr.is_unspecified() {
+        reply.yiaddr = Ipv4Addr::UNSPECIFIED;
+        reply
+            .options
+            .retain(|option| option.tp != DHCPOPTION::IPAddressLeaseTime);
+    }
+    reply
+        .set_option_value(
+            DHCPOPTION::RouterOption,
+            DhcpOptionValue::Addresses(vec![SERVER]),
+        )
+        .unwrap();
+    reply
+}
+
+async fn receive(server: &mut DhcpMemoryServer) -> (DHCPMessage, DhcpDestination) {
+    let (datagram, destination) = server.recv().await.unwrap();
+    (DHCPMessage::try_from(&datagram[..]).unwrap(), destination)
+}
+
+#[tokio::test]
+async fn acquires_and_releases_a_lease() {
+    let (transport, mut server) = DhcpMemoryTransport::pair(None);
+    let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+    let mut client = DhcpClient::new(config());
+    let mut events = client.subscribe();
+    let client = tokio::spawn(async move { client.run(&transport, stopped).await.map(|_| client) });
+
+    let (discover, destination) = receive(&mut server).await;
+    assert_eq!(discover.message_type(), Some(DHCPMessageType::DHCPDISCOVER));
+    assert_eq!(destination, DhcpDestination::Broadcast);
+    assert_eq!(discover.max_message_size(), Some(DHCP_MAX_MESSAGE_SIZE));
+    let offer = routed_reply(&discover, DHCPMessageType::DHCPOFFER);
+    server.send(SERVER, &offer.to_bytes()).unwrap();
+
+    let (request, destination) = receive(&mut server).await;
+    assert_eq!(request.message_type(), Some(DHCPMessageType::DHCPREQUEST));
+    assert_eq!(destination, DhcpDestination::Broadcast);
+    assert_eq!(request.requested_ip_address(), Some(ADDRESS));
+    assert_eq!(request.server_identifier(), Some(SERVER));
+    let ack = routed_reply(&request, DHCPMessageType::DHCPACK);
+    server.send(SERVER, &ack.to_bytes()).unwrap();
+
+    // let the client take the ACK before shutting it down
+    tokio::task::yield_now().await;
+    stop.send(()).unwrap();
+    let (release, destination) = receive(&mut server).await;
+    assert_eq!(release.message_type(), Some(DHCPMessageType::DHCPRELEASE));
+    assert_eq!(destination, DhcpDestination::Unicast(SERVER));
+    assert_eq!(release.ciaddr, ADDRESS);
+    assert_eq!(request.max_message_size(), Some(DHCP_MAX_MESSAGE_SIZE));
+    assert_eq!(release.max_message_size(), None);
+
+    let client = client.await.unwrap().unwrap();
+    assert_eq!(client.state(), DhcpState::Init);
+    assert!(client.lease().is_none());
+
+    assert!(matches!(
+        events.recv().await.unwrap(),
+        DhcpEvent::OfferReceived(received) if *received == offer
+    ));
+    assert!(matches!(
+        events.recv().await.unwrap(),
+        DhcpEvent::Bound(lease) if lease.address == ADDRESS
+    ));
+    assert!(matches!(
+        events.recv().await.unwrap(),
+        DhcpEvent::Released(lease) if lease.address == ADDRESS
+    ));
+}
+
+#[tokio::test]
+async fn inform_returns_configuration_without_a_lease() {
+    let (transport, mut server) = DhcpMemoryTransport::pair(None);
+    let client = tokio::spawn(async move {
+        let mut client = DhcpClient::new(DhcpClientConfig::new(MAC));
+        client.inform(&transport, ADDRESS).await
+    });
+
+    let (inform, _) = receive(&mut server).await;
+    assert_eq!(inform.message_type(), Some(DHCPMessageType::DHCPINFORM));
+    assert_eq!(inform.ciaddr, ADDRESS);
+    assert_eq!(inform.requested_ip_address(), None);
+    let ack = routed_reply(&inform, DHCPMessageType::DHCPACK);
+    server.send(SERVER, &ack.to_bytes()).unwrap();
+
+    let ack = client.await.unwrap().unwrap();
+    assert_eq!(ack.routers(), vec![SERVER]);
+    assert_eq!(ack.lease_time(), None);
+}
@@ -0,0 +1,101 @@
+// Changes the interface named by INODE_TEST_IFACE, so it is ignored by
+// default. Run it as root against one end of a veth pair in a namespace:
+//
+//   ip netns add inode
+//   ip -n inode link add veth0 type veth peer name veth1
+//   ip -n inode link set veth0 up
+//   ip -n inode link set veth1 up
+//   ip netns exec inode env INODE_TEST_IFACE=veth0 \
+//       cargo test -p packet --test dhcp_netlink -- --ignored
+#![cfg(target_os = "linux")]
+
+use std::fs;
+use std::net::Ipv4Addr;
+
+use packet::udp::dhcp::client::DhcpLease;
+use packet::udp::dhcp::netlink::DhcpNetlink;
+use packet::udp::dhcp::*;
+use pnet::datalink;
+use pnet::ipnetwork::IpNetwork;
+
+mod common;
+use common::option;
+
+const ADDRESS: Ipv4Addr = Ipv4Addr::new(10, 77, 0, 10);
+const ROUTER: Ipv4Addr = Ipv4Addr::new(10, 77, 0, 1);
+
+fn lease() -> DhcpLease {
+    let options = vec![
+        option(
+            DHCPOPTION::SubnetMask,
+            DhcpOptionValue::Address(Ipv4Addr::new(255, 255, 255, 0)),
+        ),
+        option(
+            DHCPOPTION::RouterOption,
+            DhcpOptionValue::Addresses(vec![ROUTER]),
+        ),
+        option(DHCPOPTION::InterfaceMTUOption, DhcpOptionValue::U16(1400)),
+    ];
+    common::lease(ADDRESS, ROUTER, options)
+}
+
+fn addresses(interface: &str) -> Vec<IpNetwork> {
+    datalink::interfaces()
+        .into_iter()
+        .find(|ele| ele.name == interface)
+        .map(|ele| ele.ips)
+        .unwrap_or_default()
+}
+
+fn mtu(interface: &str) -> u32 {
+    fs::read_to_string(format!("/sys/class/net/{}/mtu", interface))
+        .unwrap()
+        .trim()
+        .parse()
+        .unwrap()
+}
+
+// gateways of the default routes through interface
+fn default_routes(interface: &str) -> Vec<Ipv4Addr> {
+    fs::read_to_string("/proc/net/route")
+        .unwrap()
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields[0] != interface || fields[1] != "00000000" {
+                return None;
+            }
+            let gateway = u32::from_str_radix(fields[2], 16).ok()?;
+            Some(Ipv4Addr::from(gateway.swap_bytes()))
+        })
+        .collect()
+}
+
+#[tokio::test]
+#[ignore]
+async fn applies_and_removes_a_lease() {
+    let interface = std::env::var("INODE_TEST_IFACE").expect("INODE_TEST_IFACE");
+    let mut netlink = DhcpNetlink::connect(&interface).unwrap();
+    let lease = lease();
+    let network = IpNetwork::new(ADDRESS.into(), 24).unwrap();
+    let original = mtu(&interface);
+
+    netlink.apply(&lease).await.unwrap();
+    assert!(addresses(&interface).contains(&network));
+    assert_eq!(default_routes(&interface), vec![ROUTER]);
+    assert_eq!(mtu(&interface), 1400);
+    assert_eq!(netlink.mtu, Some(original));
+
+    // a renewal applies the same lease again and keeps the first MTU
+    netlink.apply(&lease).await.unwrap();
+    assert_eq!(netlink.mtu, Some(original));
+
+    netlink.remove(&lease).await.unwrap();
+    assert!(!addresses(&interface).contains(&network));
+    assert!(default_routes(&interface).is_empty());
+    assert_eq!(mtu(&interface), original);
+
+    // nothing left to remove is fine
+    netlink.remove(&lease).await.unwrap();
+}
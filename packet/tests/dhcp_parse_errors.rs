@@ -0,0 +1,153 @@
+use packet::udp::dhcp::*;
+
+mod common;
+use common::*;
+
+// offsets into the fixed header
+const HLEN: usize = 2;
+const FILE: usize = 108;
+
+// a DISCOVER with its header and magic cookie but no options
+fn header() -> Vec<u8> {
+    DHCPMessage::new(DHCPMessageType::DHCPDISCOVER, MAC).to_bytes()[..DHCP_HEADER_LEN + 4].to_vec()
+}
+
+fn with_options(options: &[u8]) -> Vec<u8> {
+    let mut bytes = header();
+    bytes.extend_from_slice(options);
+    bytes
+}
+
+#[test]
+fn malformed_messages_are_errors() {
+    let mut bad_cookie = with_options(&[0xff]);
+    bad_cookie[DHCP_HEADER_LEN..DHCP_HEADER_LEN + 4].copy_from_slice(&[1, 2, 3, 4]);
+    let mut bad_hlen = with_options(&[0xff]);
+    bad_hlen[HLEN] = 16;
+    // the overloaded file field holds an option running past its 128 bytes
+    let mut overloaded = with_options(&[0x34, 1, DHCP_OVERLOAD_FILE, 0xff]);
+    overloaded[FILE..FILE + 2].copy_from_slice(&[0x0c, 200]);
+    let cases: Vec<(&str, Vec<u8>, DHCPParseError)> = vec![
+        ("empty", Vec::new(), DHCPParseError::TruncatedHeader(0)),
+        (
+            "short header",
+            header()[..DHCP_HEADER_LEN + 3].to_vec(),
+            DHCPParseError::TruncatedHeader(DHCP_HEADER_LEN + 3),
+        ),
+        (
+            "wrong cookie",
+            bad_cookie,
+            DHCPParseError::BadMagicCookie(0x01020304),
+        ),
+        ("no options", header(), DHCPParseError::MissingEnd),
+        (
+            "no end option",
+            with_options(&[0x35, 1, 1]),
+            DHCPParseError::MissingEnd,
+        ),
+        (
+            "missing length",
+            with_options(&[0x0c]),
+            DHCPParseError::OptionOverrun(0x0c),
+        ),
+        (
+            "length past the buffer",
+            with_options(&[0x35, 5, 1]),
+            DHCPParseError::OptionOverrun(0x35),
+        ),
+        (
+            "length past the overloaded file",
+            overloaded,
+            DHCPParseError::OptionOverrun(0x0c),
+        ),
+        ("hlen 16", bad_hlen, DHCPParseError::BadHlen(16)),
+    ];
+    for (name, bytes, error) in cases {
+        assert_eq!(DHCPMessage::try_from(&bytes[..]), Err(error), "{}", name);
+    }
+}
+
+#[test]
+fn malformed_option_values_are_errors() {
+    let cases: Vec<(DHCPOPTION, Vec<u8>)> = vec![
+        (DHCPOPTION::SubnetMask, vec![255, 255, 255]),
+        (DHCPOPTION::RouterOption, vec![10, 0, 0, 1, 10]),
+        (DHCPOPTION::StaticRouteOption, vec![10, 0, 0, 0]),
+        (DHCPOPTION::IPAddressLeaseTime, vec![0, 1]),
+        (DHCPOPTION::TimeOffset, vec![0, 0, 0, 0, 0]),
+        (DHCPOPTION::OptionOverload, Vec::new()),
+        (DHCPOPTION::InterfaceMTUOption, vec![5]),
+        (DHCPOPTION::PathMTUPlateauTableOption, vec![1, 2, 3]),
+        (DHCPOPTION::DomainName, vec![0xc3, 0x28]),
+        (DHCPOPTION::IPForwardingOption, vec![2]),
+        (DHCPOPTION::DHCPMessageType, vec![0x42]),
+        (DHCPOPTION::ClientIdentifier, vec![1]),
+        (DHCPOPTION::DomainSearch, vec![3, b'a']),
+        (DHCPOPTION::DomainSearch, vec![1, b'a', 0xc0, 0]),
+        (DHCPOPTION::ClasslessStaticRoute, vec![33, 10, 0, 0, 0]),
+        (DHCPOPTION::ClasslessStaticRoute, vec![24, 10, 0, 0, 192]),
+    ];
+    for (tp, va) in cases {
+        assert_eq!(
+            DhcpOptionValue::decode(tp, &va),
+            Err(DHCPParseError::BadOptionValue(tp.into())),
+            "{:?} {:?}",
+            tp,
+            va
+        );
+    }
+}
+
+#[test]
+fn unencodable_option_values_are_errors() {
+    let router = "10.0.0.1".parse().unwrap();
+    let cases = vec![
+        (
+            DHCPOPTION::ClasslessStaticRoute,
+            DhcpOptionValue::ClasslessRoutes(vec![("10.0.0.0".parse().unwrap(), 33, router)]),
+        ),
+        (
+            DHCPOPTION::DomainSearch,
+            DhcpOptionValue::DomainList(vec![format!("{}.example", "a".repeat(64))]),
+        ),
+    ];
+    for (tp, value) in cases {
+        assert_eq!(value.encode(), None, "{:?}", value);
+        assert_eq!(
+            DHCPOPTIONS::from_value(tp, &value),
+            Err(DHCPParseError::BadOptionValue(tp.into()))
+        );
+        let mut message = DHCPMessage::new(DHCPMessageType::DHCPOFFER, MAC);
+        assert!(message.set_option_value(tp, value).is_err());
+        assert_eq!(message.option(tp), None);
+    }
+    // the longest label and prefix still fit
+    let longest = DhcpOptionValue::DomainList(vec!["a".repeat(63)]);
+    assert_eq!(longest.encode().unwrap().len(), 1 + 63 + 1);
+    let host = DhcpOptionValue::ClasslessRoutes(vec![(router, 32, router)]);
+    assert_eq!(host.encode().unwrap(), vec![32, 10, 0, 0, 1, 10, 0, 0, 1]);
+}
+
+#[test]
+fn unknown_options_round_trip_byte_exact() {
+    // an empty value and values holding pad and end bytes, then the end
+    let options = [
+        0x3e, 3, 1, 2, 3, 0x7f, 0, 0xfe, 4, 0, 0xff, 0x80, 0x01, 0xff,
+    ];
+    let bytes = with_options(&options);
+    let message = DHCPMessage::try_from(&bytes[..]).unwrap();
+    let codes: Vec<DHCPOPTION> = message.options.iter().map(|option| option.tp).collect();
+    assert_eq!(
+        codes,
+        vec![
+            DHCPOPTION::Unknown(0x3e),
+            DHCPOPTION::Unknown(0x7f),
+            DHCPOPTION::Unknown(0xfe)
+        ]
+    );
+    assert_eq!(
+        message.option_value(DHCPOPTION::Unknown(0xfe)),
+        Some(DhcpOptionValue::Bytes(vec![0, 0xff, 0x80, 0x01]))
+    );
+    assert_eq!(&message.to_bytes()[..], &bytes[..]);
+}
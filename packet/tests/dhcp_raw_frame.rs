@@ -0,0 +1,79 @@
+use std::net::{Ipv4Addr, SocketAddrV4};
+
+use packet::udp::dhcp::client::{DHCP_CLIENT_PORT, DHCP_SERVER_PORT};
+use packet::udp::dhcp::raw::*;
+use pnet::util::MacAddr;
+
+mod common;
+use common::*;
+
+const SERVER_MAC: MacAddr = MacAddr(0x02, 0, 0, 0, 0, 1);
+const PAYLOAD: &[u8] = b"DHCP reply";
+// offsets into the frame
+const IP: usize = 14;
+const IP_TOTAL_LENGTH: usize = IP + 2;
+const IP_FLAGS_FRAGMENT: usize = IP + 6;
+const UDP_DESTINATION: usize = IP + 20 + 2;
+
+fn server() -> SocketAddrV4 {
+    SocketAddrV4::new(Ipv4Addr::new(192, 168, 1, 1), DHCP_SERVER_PORT)
+}
+
+fn reply_to(mac: MacAddr) -> Vec<u8> {
+    let client = SocketAddrV4::new(Ipv4Addr::BROADCAST, DHCP_CLIENT_PORT);
+    udp_frame((SERVER_MAC, server()), (mac, client), PAYLOAD)
+}
+
+#[test]
+fn accepts_replies_to_us_and_to_broadcast() {
+    let filter = DhcpFrameFilter::new(MAC);
+    for mac in [MAC, MacAddr::broadcast()] {
+        let frame = reply_to(mac);
+        assert_eq!(
+            filter.matches(&frame),
+            Some(DhcpFrame {
+                source_mac: SERVER_MAC,
+                source: server(),
+                payload: PAYLOAD,
+            })
+        );
+    }
+    // short frames are padded to the Ethernet minimum
+    let mut padded = reply_to(MAC);
+    padded.resize(128, 0);
+    assert_eq!(filter.matches(&padded).unwrap().payload, PAYLOAD);
+}
+
+#[test]
+fn rejects_frames_not_for_the_client() {
+    let filter = DhcpFrameFilter::new(MAC);
+    let mut wrong_port = reply_to(MAC);
+    wrong_port[UDP_DESTINATION..UDP_DESTINATION + 2].copy_from_slice(&67u16.to_be_bytes());
+    let other_mac = reply_to(MacAddr(0x02, 0, 0, 0, 0, 2));
+    let mut more_fragments = reply_to(MAC);
+    more_fragments[IP_FLAGS_FRAGMENT] |= 0x20;
+    let mut fragment_offset = reply_to(MAC);
+    fragment_offset[IP_FLAGS_FRAGMENT + 1] = 1;
+    let frame = reply_to(MAC);
+    let truncated_ip = frame[..IP + 10].to_vec();
+    let truncated_udp = frame[..frame.len() - PAYLOAD.len() - 4].to_vec();
+    let mut too_long = reply_to(MAC);
+    let total = u16::from_be_bytes([too_long[IP_TOTAL_LENGTH], too_long[IP_TOTAL_LENGTH + 1]]);
+    too_long[IP_TOTAL_LENGTH..IP_TOTAL_LENGTH + 2].copy_from_slice(&(total + 1).to_be_bytes());
+    let mut too_short = reply_to(MAC);
+    too_short[IP_TOTAL_LENGTH..IP_TOTAL_LENGTH + 2].copy_from_slice(&24u16.to_be_bytes());
+    let cases = [
+        ("destination port", wrong_port),
+        ("another MAC", other_mac),
+        ("more fragments", more_fragments),
+        ("fragment offset", fragment_offset),
+        ("truncated IPv4 header", truncated_ip),
+        ("truncated UDP payload", truncated_udp),
+        ("total length past the frame", too_long),
+        ("total length without UDP header", too_short),
+        ("empty", Vec::new()),
+    ];
+    for (name, frame) in cases {
+        assert_eq!(filter.matches(&frame), None, "{}", name);
+    }
+}
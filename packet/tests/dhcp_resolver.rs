@@ -0,0 +1,115 @@
+use std::fs;
+use std::net::Ipv4Addr;
+use std::path::PathBuf;
+
+use packet::udp::dhcp::resolver::*;
+
+const ORIGINAL: &str = "nameserver 192.0.2.53\n";
+
+fn settings() -> DhcpDnsSettings {
+    DhcpDnsSettings {
+        servers: vec![Ipv4Addr::new(10, 0, 0, 53), Ipv4Addr::new(10, 0, 0, 54)],
+        search: vec!["corp.example".to_string(), "example".to_string()],
+    }
+}
+
+// resolv.conf and its backup in a directory of their own
+fn resolv_conf(name: &str) -> (PathBuf, DhcpResolvConfFile) {
+    let dir = std::env::temp_dir().join(format!("inode-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let file = DhcpResolvConfFile {
+        path: dir.join("resolv.conf"),
+        backup: Some(dir.join("resolv.conf.orig")),
+    };
+    (dir, file)
+}
+
+#[test]
+fn writes_search_and_nameservers() {
+    assert_eq!(
+        settings().resolv_conf("eth0"),
+        "# generated by inode for eth0\n\
+         search corp.example example\n\
+         nameserver 10.0.0.53\n\
+         nameserver 10.0.0.54\n"
+    );
+    assert_eq!(
+        DhcpDnsSettings::default().resolv_conf("eth0"),
+        "# generated by inode for eth0\n"
+    );
+}
+
+#[test]
+fn backup_defaults_to_an_inode_suffix() {
+    let file = DhcpResolvConfFile::default();
+    assert_eq!(file.backup_path(), PathBuf::from("/etc/resolv.conf.inode"));
+}
+
+#[tokio::test]
+async fn restores_the_original_file() {
+    let (dir, file) = resolv_conf("resolver-original");
+    fs::write(&file.path, ORIGINAL).unwrap();
+
+    file.apply("eth0", &settings()).await.unwrap();
+    assert_eq!(
+        fs::read_to_string(&file.path).unwrap(),
+        settings().resolv_conf("eth0")
+    );
+    assert_eq!(fs::read_to_string(file.backup_path()).unwrap(), ORIGINAL);
+
+    file.revert("eth0").await.unwrap();
+    assert_eq!(fs::read_to_string(&file.path).unwrap(), ORIGINAL);
+    assert!(!file.backup_path().exists());
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn removes_the_file_when_there_was_none() {
+    let (dir, file) = resolv_conf("resolver-none");
+
+    file.apply("eth0", &settings()).await.unwrap();
+    assert!(file.path.exists());
+    assert!(!file.backup_path().exists());
+
+    file.revert("eth0").await.unwrap();
+    assert!(!file.path.exists());
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn applying_again_keeps_the_backup() {
+    let (dir, file) = resolv_conf("resolver-twice");
+    fs::write(&file.path, ORIGINAL).unwrap();
+
+    file.apply("eth0", &settings()).await.unwrap();
+    let renewed = DhcpDnsSettings {
+        servers: vec![Ipv4Addr::new(10, 0, 0, 55)],
+        search: Vec::new(),
+    };
+    file.apply("eth0", &renewed).await.unwrap();
+    assert_eq!(
+        fs::read_to_string(&file.path).unwrap(),
+        renewed.resolv_conf("eth0")
+    );
+    assert_eq!(fs::read_to_string(file.backup_path()).unwrap(), ORIGINAL);
+
+    file.revert("eth0").await.unwrap();
+    assert_eq!(fs::read_to_string(&file.path).unwrap(), ORIGINAL);
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn leaves_a_file_that_is_not_ours() {
+    let (dir, file) = resolv_conf("resolver-foreign");
+    fs::write(&file.path, ORIGINAL).unwrap();
+    file.apply("eth0", &settings()).await.unwrap();
+
+    // something else rewrote resolv.conf after us
+    let foreign = "nameserver 198.51.100.53\n";
+    fs::write(&file.path, foreign).unwrap();
+    file.revert("eth0").await.unwrap();
+    assert_eq!(fs::read_to_string(&file.path).unwrap(), foreign);
+    assert_eq!(fs::read_to_string(file.backup_path()).unwrap(), ORIGINAL);
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,81 @@
+use std::time::{Duration, Instant};
+
+use packet::udp::dhcp::client::*;
+use packet::udp::dhcp::supervisor::*;
+use packet::udp::dhcp::transport::*;
+use packet::udp::dhcp::*;
+use tokio::sync::{mpsc, oneshot};
+
+mod common;
+use common::*;
+
+async fn receive(server: &mut DhcpMemoryServer) -> DHCPMessage {
+    let (datagram, _) = server.recv().await.unwrap();
+    DHCPMessage::try_from(&datagram[..]).unwrap()
+}
+
+#[tokio::test]
+async fn failed_client_is_restarted_and_keeps_reporting() {
+    let mut config = config();
+    config.interface = Some("test0".to_string());
+    let mut supervisor = DhcpSupervisor::default();
+    supervisor.clients.push(config);
+    let mut events = supervisor.subscribe();
+
+    // every (re)start binds a new memory transport, its server end comes here
+    let (servers, mut bound) = mpsc::unbounded_channel();
+    let bind = move |config: &DhcpClientConfig| {
+        let (transport, server) = DhcpMemoryTransport::pair(config.interface.as_deref());
+        let _ = servers.send(server);
+        Ok(transport)
+    };
+    let (stop, stopped) = oneshot::channel::<()>();
+    let supervisor = tokio::spawn(supervisor.run_with(bind, stopped));
+
+    // the server going away fails the first client
+    let mut server = bound.recv().await.unwrap();
+    let discover = receive(&mut server).await;
+    assert_eq!(discover.message_type(), Some(DHCPMessageType::DHCPDISCOVER));
+    let failed = Instant::now();
+    drop(server);
+
+    let mut server = bound.recv().await.unwrap();
+    let waited = failed.elapsed();
+    assert!(
+        waited >= Duration::from_secs(DHCP_SUPERVISOR_MIN_DELAY_SECS),
+        "restarted after {:?}",
+        waited
+    );
+    let discover = receive(&mut server).await;
+    let offer = reply(&discover, DHCPMessageType::DHCPOFFER, SERVER);
+    server.send(SERVER, &offer.to_bytes()).unwrap();
+    let request = receive(&mut server).await;
+    let ack = reply(&request, DHCPMessageType::DHCPACK, SERVER);
+    server.send(SERVER, &ack.to_bytes()).unwrap();
+
+    // events of both instances, tagged with the interface
+    let mut offers = 0;
+    loop {
+        let (interface, event) = events.recv().await.unwrap();
+        assert_eq!(interface, "test0");
+        match event {
+            DhcpEvent::OfferReceived(_) => offers += 1,
+            DhcpEvent::TransportError(_) => assert_eq!(offers, 0),
+            DhcpEvent::Bound(lease) => {
+                assert_eq!(lease.address, ADDRESS);
+                break;
+            }
+            event => panic!("unexpected {:?}", event),
+        }
+    }
+    assert_eq!(offers, 1);
+
+    stop.send(()).unwrap();
+    let release = receive(&mut server).await;
+    assert_eq!(release.message_type(), Some(DHCPMessageType::DHCPRELEASE));
+    supervisor.await.unwrap();
+    assert!(matches!(
+        events.recv().await.unwrap(),
+        (_, DhcpEvent::Released(lease)) if lease.address == ADDRESS
+    ));
+}
@@ -1,92 +1,113 @@
 use std::convert::TryInto;
+use std::fmt;
+use std::net::Ipv4Addr;
 
-use bytes::{Buf, BufMut, Bytes, BytesMut};
+use bytes::{Buf, BufMut, BytesMut};
+use pnet::util::MacAddr;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
-#[repr(u8)]
+pub mod client;
+pub mod duid;
+pub mod hook;
+pub mod lease;
+#[cfg(target_os = "linux")]
+pub mod netlink;
+pub mod raw;
+pub mod resolver;
+pub mod supervisor;
+pub mod transport;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum DHCPOPTION {
-    PadOption = 0x00,
-    EndOption = 0xff,
-    SubnetMask = 0x01,
-    TimeOffset = 0x02,
-    RouterOption = 0x03,
-    TimeServerOption = 0x04,
-    NameServerOption = 0x05,
-    DomainNameServerOption = 0x06,
-    LogServerOption = 0x07,
-    CookieServerOption = 0x08,
-    LPRServerOption = 0x09,
-    ImpressServerOption = 0x0A,
-    ResourceLocationServerOption = 0x0B,
-    HostNameOption = 0x0C,
-    BootFileSizeOption = 0x0D,
-    MeritDumpFile = 0x0E,
-    DomainName = 0x0F,
-    SwapServer = 0x10,
-    RootPath = 0x11,
-    ExtensionsPath = 0x12,
-    IPForwardingOption = 0x13,
-    NonLocalSourceRoutingOption = 0x14,
-    PolicyFilterOption = 0x15,
-    MaximumDatagramReassemblySize = 0x16,
-    DefaultIPTimeTolive = 0x17,
-    PathMTUAgingTimeoutOption = 0x18,
-    PathMTUPlateauTableOption = 0x19,
-    InterfaceMTUOption = 0x1A,
-    AllSubnetsAreLocalOption = 0x1B,
-    BroadcastAddressOption = 0x1C,
-    PerformMaskDiscoveryOption = 0x1D,
-    MaskSupplierOption = 0x1E,
-    PerformRouterDiscoveryOption = 0x1F,
-    RouterSolicitationAddressOption = 0x20,
-    StaticRouteOption = 0x21,
-    TrailerEncapsulationOption = 0x22,
-    ARPCacheTimeoutOption = 0x23,
-    EthernetEncapsulationOption = 0x24,
-    TCPDefaultTTLOption = 0x25,
-    TCPKeepaliveIntervalOption = 0x26,
-    TCPKeepaliveGarbageOption = 0x27,
-    NetworkInformationServiceDomainOption = 0x28,
-    NetworkInformationServersOption = 0x29,
-    NetworkTimeProtocolServersOption = 0x2A,
-    VendorSpecificInformation = 0x2B,
-    NetBIOSOverTCPIPNameServerOption = 0x2C,
-    NetBIOSOverTCPIPDatagramDistributionServerOption = 0x2D,
-    NetBIOSOverTCPIPNodeTypeOption = 0x2E,
-    NetBIOSOverTCPIPScopeOption = 0x2F,
-    XWindowSystemFontServerOption = 0x30,
-    XWindowSystemDisplayManagerOption = 0x31,
-    RequestedIPAddress = 0x32,
-    IPAddressLeaseTime = 0x33,
-    OptionOverload = 0x34,
-    DHCPMessageType = 0x35,
-    ServerIdentifier = 0x36,
-    ParameterRequestList = 0x37,
-    Message = 0x38,
-    MaximumDHCPMessageSize = 0x39,
-    RenewalTimeValue = 0x3A,
-    RebindingTimeValue = 0x3B,
-    VendorClassIdentifier = 0x3C,
-    ClientIdentifier = 0x3D,
-    NetworkInformationServicePlusDomainOption = 0x40,
-    NetworkInformationServicePlusServersOption = 0x41,
-    TFTPServerName = 0x42,
-    BootfileName = 0x43,
-    MobileIPHomeAgentOption = 0x44,
-    SMTPServerOption = 0x45,
-    POP3ServerOption = 0x46,
-    NNTPServerOption = 0x47,
-    DefaultWWWServerOption = 0x48,
-    DefaultFingerServerOption = 0x49,
-    DefaultIRCServerOption = 0x4A,
-    StreetTalkServerOption = 0x4B,
-    StreetTalkDirectoryAssistanceServerOption = 0x4C,
-}
-
-impl Into<DHCPOPTION> for u8 {
-    fn into(self) -> DHCPOPTION {
-        match self {
+    PadOption,
+    EndOption,
+    SubnetMask,
+    TimeOffset,
+    RouterOption,
+    TimeServerOption,
+    NameServerOption,
+    DomainNameServerOption,
+    LogServerOption,
+    CookieServerOption,
+    LPRServerOption,
+    ImpressServerOption,
+    ResourceLocationServerOption,
+    HostNameOption,
+    BootFileSizeOption,
+    MeritDumpFile,
+    DomainName,
+    SwapServer,
+    RootPath,
+    ExtensionsPath,
+    IPForwardingOption,
+    NonLocalSourceRoutingOption,
+    PolicyFilterOption,
+    MaximumDatagramReassemblySize,
+    DefaultIPTimeTolive,
+    PathMTUAgingTimeoutOption,
+    PathMTUPlateauTableOption,
+    InterfaceMTUOption,
+    AllSubnetsAreLocalOption,
+    BroadcastAddressOption,
+    PerformMaskDiscoveryOption,
+    MaskSupplierOption,
+    PerformRouterDiscoveryOption,
+    RouterSolicitationAddressOption,
+    StaticRouteOption,
+    TrailerEncapsulationOption,
+    ARPCacheTimeoutOption,
+    EthernetEncapsulationOption,
+    TCPDefaultTTLOption,
+    TCPKeepaliveIntervalOption,
+    TCPKeepaliveGarbageOption,
+    NetworkInformationServiceDomainOption,
+    NetworkInformationServersOption,
+    NetworkTimeProtocolServersOption,
+    VendorSpecificInformation,
+    NetBIOSOverTCPIPNameServerOption,
+    NetBIOSOverTCPIPDatagramDistributionServerOption,
+    NetBIOSOverTCPIPNodeTypeOption,
+    NetBIOSOverTCPIPScopeOption,
+    XWindowSystemFontServerOption,
+    XWindowSystemDisplayManagerOption,
+    RequestedIPAddress,
+    IPAddressLeaseTime,
+    OptionOverload,
+    DHCPMessageType,
+    ServerIdentifier,
+    ParameterRequestList,
+    Message,
+    MaximumDHCPMessageSize,
+    RenewalTimeValue,
+    RebindingTimeValue,
+    VendorClassIdentifier,
+    ClientIdentifier,
+    NetworkInformationServicePlusDomainOption,
+    NetworkInformationServicePlusServersOption,
+    TFTPServerName,
+    BootfileName,
+    MobileIPHomeAgentOption,
+    SMTPServerOption,
+    POP3ServerOption,
+    NNTPServerOption,
+    DefaultWWWServerOption,
+    DefaultFingerServerOption,
+    DefaultIRCServerOption,
+    StreetTalkServerOption,
+    StreetTalkDirectoryAssistanceServerOption,
+    UserClass,
+    RapidCommit,
+    ClientFQDN,
+    RelayAgentInformation,
+    DomainSearch,
+    ClasslessStaticRoute,
+    WebProxyAutoDiscovery,
+    Unknown(u8),
+}
+
+impl From<u8> for DHCPOPTION {
+    fn from(value: u8) -> Self {
+        match value {
             0x00 => DHCPOPTION::PadOption,
             0xff => DHCPOPTION::EndOption,
             0x01 => DHCPOPTION::SubnetMask,
@@ -163,19 +184,140 @@ impl Into<DHCPOPTION> for u8 {
             0x4A => DHCPOPTION::DefaultIRCServerOption,
             0x4B => DHCPOPTION::StreetTalkServerOption,
             0x4C => DHCPOPTION::StreetTalkDirectoryAssistanceServerOption,
-            _ => DHCPOPTION::EndOption,
+            0x4D => DHCPOPTION::UserClass,
+            0x50 => DHCPOPTION::RapidCommit,
+            0x51 => DHCPOPTION::ClientFQDN,
+            0x52 => DHCPOPTION::RelayAgentInformation,
+            0x77 => DHCPOPTION::DomainSearch,
+            0x79 => DHCPOPTION::ClasslessStaticRoute,
+            0xFC => DHCPOPTION::WebProxyAutoDiscovery,
+            _ => DHCPOPTION::Unknown(value),
+        }
+    }
+}
+
+impl From<DHCPOPTION> for u8 {
+    fn from(value: DHCPOPTION) -> Self {
+        match value {
+            DHCPOPTION::PadOption => 0x00,
+            DHCPOPTION::EndOption => 0xff,
+            DHCPOPTION::SubnetMask => 0x01,
+            DHCPOPTION::TimeOffset => 0x02,
+            DHCPOPTION::RouterOption => 0x03,
+            DHCPOPTION::TimeServerOption => 0x04,
+            DHCPOPTION::NameServerOption => 0x05,
+            DHCPOPTION::DomainNameServerOption => 0x06,
+            DHCPOPTION::LogServerOption => 0x07,
+            DHCPOPTION::CookieServerOption => 0x08,
+            DHCPOPTION::LPRServerOption => 0x09,
+            DHCPOPTION::ImpressServerOption => 0x0A,
+            DHCPOPTION::ResourceLocationServerOption => 0x0B,
+            DHCPOPTION::HostNameOption => 0x0C,
+            DHCPOPTION::BootFileSizeOption => 0x0D,
+            DHCPOPTION::MeritDumpFile => 0x0E,
+            DHCPOPTION::DomainName => 0x0F,
+            DHCPOPTION::SwapServer => 0x10,
+            DHCPOPTION::RootPath => 0x11,
+            DHCPOPTION::ExtensionsPath => 0x12,
+            DHCPOPTION::IPForwardingOption => 0x13,
+            DHCPOPTION::NonLocalSourceRoutingOption => 0x14,
+            DHCPOPTION::PolicyFilterOption => 0x15,
+            DHCPOPTION::MaximumDatagramReassemblySize => 0x16,
+            DHCPOPTION::DefaultIPTimeTolive => 0x17,
+            DHCPOPTION::PathMTUAgingTimeoutOption => 0x18,
+            DHCPOPTION::PathMTUPlateauTableOption => 0x19,
+            DHCPOPTION::InterfaceMTUOption => 0x1A,
+            DHCPOPTION::AllSubnetsAreLocalOption => 0x1B,
+            DHCPOPTION::BroadcastAddressOption => 0x1C,
+            DHCPOPTION::PerformMaskDiscoveryOption => 0x1D,
+            DHCPOPTION::MaskSupplierOption => 0x1E,
+            DHCPOPTION::PerformRouterDiscoveryOption => 0x1F,
+            DHCPOPTION::RouterSolicitationAddressOption => 0x20,
+            DHCPOPTION::StaticRouteOption => 0x21,
+            DHCPOPTION::TrailerEncapsulationOption => 0x22,
+            DHCPOPTION::ARPCacheTimeoutOption => 0x23,
+            DHCPOPTION::EthernetEncapsulationOption => 0x24,
+            DHCPOPTION::TCPDefaultTTLOption => 0x25,
+            DHCPOPTION::TCPKeepaliveIntervalOption => 0x26,
+            DHCPOPTION::TCPKeepaliveGarbageOption => 0x27,
+            DHCPOPTION::NetworkInformationServiceDomainOption => 0x28,
+            DHCPOPTION::NetworkInformationServersOption => 0x29,
+            DHCPOPTION::NetworkTimeProtocolServersOption => 0x2A,
+            DHCPOPTION::VendorSpecificInformation => 0x2B,
+            DHCPOPTION::NetBIOSOverTCPIPNameServerOption => 0x2C,
+            DHCPOPTION::NetBIOSOverTCPIPDatagramDistributionServerOption => 0x2D,
+            DHCPOPTION::NetBIOSOverTCPIPNodeTypeOption => 0x2E,
+            DHCPOPTION::NetBIOSOverTCPIPScopeOption => 0x2F,
+            DHCPOPTION::XWindowSystemFontServerOption => 0x30,
+            DHCPOPTION::XWindowSystemDisplayManagerOption => 0x31,
+            DHCPOPTION::RequestedIPAddress => 0x32,
+            DHCPOPTION::IPAddressLeaseTime => 0x33,
+            DHCPOPTION::OptionOverload => 0x34,
+            DHCPOPTION::DHCPMessageType => 0x35,
+            DHCPOPTION::ServerIdentifier => 0x36,
+            DHCPOPTION::ParameterRequestList => 0x37,
+            DHCPOPTION::Message => 0x38,
+            DHCPOPTION::MaximumDHCPMessageSize => 0x39,
+            DHCPOPTION::RenewalTimeValue => 0x3A,
+            DHCPOPTION::RebindingTimeValue => 0x3B,
+            DHCPOPTION::VendorClassIdentifier => 0x3C,
+            DHCPOPTION::ClientIdentifier => 0x3D,
+            DHCPOPTION::NetworkInformationServicePlusDomainOption => 0x40,
+            DHCPOPTION::NetworkInformationServicePlusServersOption => 0x41,
+            DHCPOPTION::TFTPServerName => 0x42,
+            DHCPOPTION::BootfileName => 0x43,
+            DHCPOPTION::MobileIPHomeAgentOption => 0x44,
+            DHCPOPTION::SMTPServerOption => 0x45,
+            DHCPOPTION::POP3ServerOption => 0x46,
+            DHCPOPTION::NNTPServerOption => 0x47,
+            DHCPOPTION::DefaultWWWServerOption => 0x48,
+            DHCPOPTION::DefaultFingerServerOption => 0x49,
+            DHCPOPTION::DefaultIRCServerOption => 0x4A,
+            DHCPOPTION::StreetTalkServerOption => 0x4B,
+            DHCPOPTION::StreetTalkDirectoryAssistanceServerOption => 0x4C,
+            DHCPOPTION::UserClass => 0x4D,
+            DHCPOPTION::RapidCommit => 0x50,
+            DHCPOPTION::ClientFQDN => 0x51,
+            DHCPOPTION::RelayAgentInformation => 0x52,
+            DHCPOPTION::DomainSearch => 0x77,
+            DHCPOPTION::ClasslessStaticRoute => 0x79,
+            DHCPOPTION::WebProxyAutoDiscovery => 0xFC,
+            DHCPOPTION::Unknown(code) => code,
         }
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct DHCPOPTIONS {
     pub tp: DHCPOPTION,
-    pub len: u8,
+    // length of the whole value, which may exceed 255 when it is split
+    // across several instances on the wire
+    pub len: u16,
     pub va: Vec<u8>,
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+impl DHCPOPTIONS {
+    pub fn new(tp: DHCPOPTION, va: Vec<u8>) -> Self {
+        DHCPOPTIONS {
+            tp,
+            len: va.len() as u16,
+            va,
+        }
+    }
+
+    pub fn from_value(tp: DHCPOPTION, value: &DhcpOptionValue) -> Result<Self, DHCPParseError> {
+        let va = value
+            .encode()
+            .ok_or(DHCPParseError::BadOptionValue(tp.into()))?;
+        Ok(DHCPOPTIONS::new(tp, va))
+    }
+
+    pub fn value(&self) -> Result<DhcpOptionValue, DHCPParseError> {
+        DhcpOptionValue::decode(self.tp, &self.va)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[repr(u8)]
 pub enum DHCPMessageType {
     DHCPDISCOVER = 0x01,
@@ -188,7 +330,43 @@ pub enum DHCPMessageType {
     DHCPINFORM = 0x08,
 }
 
-#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+impl TryFrom<u8> for DHCPMessageType {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x01 => Ok(DHCPMessageType::DHCPDISCOVER),
+            0x02 => Ok(DHCPMessageType::DHCPOFFER),
+            0x03 => Ok(DHCPMessageType::DHCPREQUEST),
+            0x04 => Ok(DHCPMessageType::DHCPDECLINE),
+            0x05 => Ok(DHCPMessageType::DHCPACK),
+            0x06 => Ok(DHCPMessageType::DHCPNAK),
+            0x07 => Ok(DHCPMessageType::DHCPRELEASE),
+            0x08 => Ok(DHCPMessageType::DHCPINFORM),
+            _ => Err(value),
+        }
+    }
+}
+
+impl From<DHCPMessageType> for u8 {
+    fn from(value: DHCPMessageType) -> Self {
+        value as u8
+    }
+}
+
+impl DHCPMessageType {
+    // DHCPOFFER, DHCPACK and DHCPNAK are sent by servers, everything else by clients
+    pub fn op(&self) -> DHCPOP {
+        match self {
+            DHCPMessageType::DHCPOFFER | DHCPMessageType::DHCPACK | DHCPMessageType::DHCPNAK => {
+                DHCPOP::BOOTREPLY
+            }
+            _ => DHCPOP::BOOTREQUEST,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
 #[repr(u8)]
 pub enum DHCPOP {
     #[default]
@@ -196,9 +374,9 @@ pub enum DHCPOP {
     BOOTREPLY = 0x02,
 }
 
-impl Into<DHCPOP> for u8 {
-    fn into(self) -> DHCPOP {
-        match self {
+impl From<u8> for DHCPOP {
+    fn from(value: u8) -> Self {
+        match value {
             0x01 => DHCPOP::BOOTREQUEST,
             0x02 => DHCPOP::BOOTREPLY,
             _ => DHCPOP::BOOTREQUEST,
@@ -212,34 +390,36 @@ impl From<DHCPOP> for u8 {
     }
 }
 
-#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
 #[repr(u8)]
 pub enum DHCPHType {
     #[default]
     ETHERNET = 0x01,
 }
 
-impl Into<DHCPHType> for u8 {
-    fn into(self) -> DHCPHType {
-        match self {
+impl From<u8> for DHCPHType {
+    fn from(value: u8) -> Self {
+        match value {
             0x01 => DHCPHType::ETHERNET,
             _ => DHCPHType::ETHERNET,
         }
     }
 }
 
-#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
 #[repr(u8)]
 pub enum DHCPHLen {
     #[default]
     ETHERNET = 0x06,
 }
 
-impl Into<DHCPHLen> for u8 {
-    fn into(self) -> DHCPHLen {
-        match self {
-            0x01 => DHCPHLen::ETHERNET,
-            _ => DHCPHLen::ETHERNET,
+impl TryFrom<u8> for DHCPHLen {
+    type Error = DHCPParseError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x06 => Ok(DHCPHLen::ETHERNET),
+            _ => Err(DHCPParseError::BadHlen(value)),
         }
     }
 }
@@ -248,184 +428,400 @@ pub trait DHCPBytes {
     fn to_bytes(&self) -> BytesMut;
 }
 
-#[derive(Debug, Clone)]
-pub struct DHCPDiscover {
-    pub op: DHCPOP,
-    pub htype: DHCPHType,
-    pub hlen: DHCPHLen,
-    pub hops: u8,
-    pub xid: u32,
-    pub secs: u16,
-    pub flags: u16,
-    pub ciaddr: u32,
-    pub yiaddr: u32,
-    pub siaddr: u32,
-    pub giaddr: u32,
-    pub chaddr: [u8; 16],
-    pub sname: [u8; 64],
-    pub file: [u8; 128],
-    pub options: Vec<u8>,
+// magic cookie dhcp read as u32 - [63, 82, 53, 63]
+pub const DHCP_MAGIC_COOKIE: u32 = 0x63825363;
+// op .. file, everything before the magic cookie
+pub const DHCP_HEADER_LEN: usize = 236;
+// IPv4 and UDP headers in front of the DHCP message
+pub const DHCP_IP_UDP_HEADER_LEN: usize = 28;
+// every DHCP participant must accept at least a 576 byte IP datagram
+pub const DHCP_MIN_MESSAGE_SIZE: usize = 576;
+
+// values of OptionOverload
+pub const DHCP_OVERLOAD_FILE: u8 = 0x01;
+pub const DHCP_OVERLOAD_SNAME: u8 = 0x02;
+// RFC 1035 2.3.4
+const DNS_MAX_LABEL_LEN: usize = 63;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DHCPParseError {
+    TruncatedHeader(usize),
+    BadMagicCookie(u32),
+    OptionOverrun(u8),
+    MissingEnd,
+    BadHlen(u8),
+    BadOptionValue(u8),
 }
 
-impl Default for DHCPDiscover {
-    fn default() -> Self {
-        DHCPDiscover {
-            op: DHCPOP::BOOTREQUEST,
-            htype: DHCPHType::ETHERNET,
-            hlen: DHCPHLen::ETHERNET,
-            hops: 0x00,
-            xid: 0x00000000,
-            secs: 0x0000,
-            flags: 0x0000,
-            ciaddr: 0x00000000,
-            yiaddr: 0x00000000,
-            siaddr: 0x00000000,
-            giaddr: 0x00000000,
-            chaddr: [0x00; 16],
-            sname: [0; 64],
-            file: [0; 128],
-            options: vec![99, 130, 83, 99],
+impl fmt::Display for DHCPParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DHCPParseError::TruncatedHeader(len) => {
+                write!(f, "truncated DHCP header: {} bytes", len)
+            }
+            DHCPParseError::BadMagicCookie(cookie) => {
+                write!(f, "bad DHCP magic cookie: {:#010x}", cookie)
+            }
+            DHCPParseError::OptionOverrun(code) => {
+                write!(f, "DHCP option {} overruns the packet", code)
+            }
+            DHCPParseError::MissingEnd => write!(f, "DHCP options without end marker"),
+            DHCPParseError::BadHlen(hlen) => write!(f, "bad DHCP hardware length: {}", hlen),
+            DHCPParseError::BadOptionValue(code) => {
+                write!(f, "malformed value for DHCP option {}", code)
+            }
         }
     }
 }
 
-impl DHCPDiscover {
-    pub fn with_mac(mac: &str) -> Self {
-        let mut mac_addr: Vec<u8> = if mac.contains("-") {
-            str::split(mac, "-")
-                .map(|u| u8::from_str_radix(u, 16).unwrap())
-                .collect()
-        } else {
-            str::split(mac, ":")
-                .map(|u| u8::from_str_radix(u, 16).unwrap())
-                .collect()
+impl std::error::Error for DHCPParseError {}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum DhcpOptionValue {
+    Address(Ipv4Addr),
+    Addresses(Vec<Ipv4Addr>),
+    AddressPairs(Vec<(Ipv4Addr, Ipv4Addr)>),
+    Duration(u32),
+    TimeOffset(i32),
+    U8(u8),
+    U16(u16),
+    U16List(Vec<u16>),
+    String(String),
+    Bool(bool),
+    MessageType(DHCPMessageType),
+    ParameterList(Vec<DHCPOPTION>),
+    ClientIdentifier { tp: u8, id: Vec<u8> },
+    DomainList(Vec<String>),
+    // destination, prefix length, router
+    ClasslessRoutes(Vec<(Ipv4Addr, u8, Ipv4Addr)>),
+    Bytes(Vec<u8>),
+}
+
+impl DhcpOptionValue {
+    pub fn decode(tp: DHCPOPTION, va: &[u8]) -> Result<Self, DHCPParseError> {
+        let bad = DHCPParseError::BadOptionValue(tp.into());
+        let value = match tp {
+            DHCPOPTION::SubnetMask
+            | DHCPOPTION::SwapServer
+            | DHCPOPTION::BroadcastAddressOption
+            | DHCPOPTION::RouterSolicitationAddressOption
+            | DHCPOPTION::RequestedIPAddress
+            | DHCPOPTION::ServerIdentifier => {
+                let octets: [u8; 4] = va.try_into().map_err(|_| bad)?;
+                DhcpOptionValue::Address(Ipv4Addr::from(octets))
+            }
+            DHCPOPTION::RouterOption
+            | DHCPOPTION::TimeServerOption
+            | DHCPOPTION::NameServerOption
+            | DHCPOPTION::DomainNameServerOption
+            | DHCPOPTION::LogServerOption
+            | DHCPOPTION::CookieServerOption
+            | DHCPOPTION::LPRServerOption
+            | DHCPOPTION::ImpressServerOption
+            | DHCPOPTION::ResourceLocationServerOption
+            | DHCPOPTION::NetworkInformationServersOption
+            | DHCPOPTION::NetworkTimeProtocolServersOption
+            | DHCPOPTION::NetBIOSOverTCPIPNameServerOption
+            | DHCPOPTION::NetBIOSOverTCPIPDatagramDistributionServerOption
+            | DHCPOPTION::XWindowSystemFontServerOption
+            | DHCPOPTION::XWindowSystemDisplayManagerOption
+            | DHCPOPTION::NetworkInformationServicePlusServersOption
+            | DHCPOPTION::MobileIPHomeAgentOption
+            | DHCPOPTION::SMTPServerOption
+            | DHCPOPTION::POP3ServerOption
+            | DHCPOPTION::NNTPServerOption
+            | DHCPOPTION::DefaultWWWServerOption
+            | DHCPOPTION::DefaultFingerServerOption
+            | DHCPOPTION::DefaultIRCServerOption
+            | DHCPOPTION::StreetTalkServerOption
+            | DHCPOPTION::StreetTalkDirectoryAssistanceServerOption => {
+                if !va.len().is_multiple_of(4) {
+                    return Err(bad);
+                }
+                DhcpOptionValue::Addresses(
+                    va.chunks_exact(4)
+                        .map(|c| Ipv4Addr::new(c[0], c[1], c[2], c[3]))
+                        .collect(),
+                )
+            }
+            DHCPOPTION::PolicyFilterOption | DHCPOPTION::StaticRouteOption => {
+                if !va.len().is_multiple_of(8) {
+                    return Err(bad);
+                }
+                DhcpOptionValue::AddressPairs(
+                    va.chunks_exact(8)
+                        .map(|c| {
+                            (
+                                Ipv4Addr::new(c[0], c[1], c[2], c[3]),
+                                Ipv4Addr::new(c[4], c[5], c[6], c[7]),
+                            )
+                        })
+                        .collect(),
+                )
+            }
+            DHCPOPTION::IPAddressLeaseTime
+            | DHCPOPTION::RenewalTimeValue
+            | DHCPOPTION::RebindingTimeValue
+            | DHCPOPTION::PathMTUAgingTimeoutOption
+            | DHCPOPTION::ARPCacheTimeoutOption
+            | DHCPOPTION::TCPKeepaliveIntervalOption => {
+                DhcpOptionValue::Duration(u32::from_be_bytes(va.try_into().map_err(|_| bad)?))
+            }
+            DHCPOPTION::TimeOffset => {
+                DhcpOptionValue::TimeOffset(i32::from_be_bytes(va.try_into().map_err(|_| bad)?))
+            }
+            DHCPOPTION::DefaultIPTimeTolive
+            | DHCPOPTION::TCPDefaultTTLOption
+            | DHCPOPTION::NetBIOSOverTCPIPNodeTypeOption
+            | DHCPOPTION::OptionOverload => match va {
+                [v] => DhcpOptionValue::U8(*v),
+                _ => return Err(bad),
+            },
+            DHCPOPTION::BootFileSizeOption
+            | DHCPOPTION::MaximumDatagramReassemblySize
+            | DHCPOPTION::InterfaceMTUOption
+            | DHCPOPTION::MaximumDHCPMessageSize => {
+                DhcpOptionValue::U16(u16::from_be_bytes(va.try_into().map_err(|_| bad)?))
+            }
+            DHCPOPTION::PathMTUPlateauTableOption => {
+                if va.is_empty() || !va.len().is_multiple_of(2) {
+                    return Err(bad);
+                }
+                DhcpOptionValue::U16List(
+                    va.chunks_exact(2)
+                        .map(|c| u16::from_be_bytes([c[0], c[1]]))
+                        .collect(),
+                )
+            }
+            DHCPOPTION::HostNameOption
+            | DHCPOPTION::MeritDumpFile
+            | DHCPOPTION::DomainName
+            | DHCPOPTION::RootPath
+            | DHCPOPTION::ExtensionsPath
+            | DHCPOPTION::NetworkInformationServiceDomainOption
+            | DHCPOPTION::NetBIOSOverTCPIPScopeOption
+            | DHCPOPTION::Message
+            | DHCPOPTION::NetworkInformationServicePlusDomainOption
+            | DHCPOPTION::TFTPServerName
+            | DHCPOPTION::BootfileName
+            | DHCPOPTION::WebProxyAutoDiscovery => {
+                // some servers NUL-terminate their strings
+                let va = match va.iter().position(|&b| b == 0) {
+                    Some(end) => &va[..end],
+                    None => va,
+                };
+                DhcpOptionValue::String(String::from_utf8(va.to_vec()).map_err(|_| bad)?)
+            }
+            DHCPOPTION::IPForwardingOption
+            | DHCPOPTION::NonLocalSourceRoutingOption
+            | DHCPOPTION::AllSubnetsAreLocalOption
+            | DHCPOPTION::PerformMaskDiscoveryOption
+            | DHCPOPTION::MaskSupplierOption
+            | DHCPOPTION::PerformRouterDiscoveryOption
+            | DHCPOPTION::TrailerEncapsulationOption
+            | DHCPOPTION::EthernetEncapsulationOption
+            | DHCPOPTION::TCPKeepaliveGarbageOption => match va {
+                [0] => DhcpOptionValue::Bool(false),
+                [1] => DhcpOptionValue::Bool(true),
+                _ => return Err(bad),
+            },
+            DHCPOPTION::DHCPMessageType => match va {
+                [v] => {
+                    DhcpOptionValue::MessageType(DHCPMessageType::try_from(*v).map_err(|_| bad)?)
+                }
+                _ => return Err(bad),
+            },
+            DHCPOPTION::ParameterRequestList => {
+                DhcpOptionValue::ParameterList(va.iter().map(|&code| code.into()).collect())
+            }
+            DHCPOPTION::ClientIdentifier => match va.split_first() {
+                Some((&tp, id)) if !id.is_empty() => DhcpOptionValue::ClientIdentifier {
+                    tp,
+                    id: id.to_vec(),
+                },
+                _ => return Err(bad),
+            },
+            DHCPOPTION::DomainSearch => {
+                DhcpOptionValue::DomainList(decode_domain_list(va).ok_or(bad)?)
+            }
+            DHCPOPTION::ClasslessStaticRoute => {
+                DhcpOptionValue::ClasslessRoutes(decode_classless_routes(va).ok_or(bad)?)
+            }
+            DHCPOPTION::PadOption
+            | DHCPOPTION::EndOption
+            | DHCPOPTION::VendorSpecificInformation
+            | DHCPOPTION::VendorClassIdentifier
+            | DHCPOPTION::UserClass
+            | DHCPOPTION::RapidCommit
+            | DHCPOPTION::ClientFQDN
+            | DHCPOPTION::RelayAgentInformation
+            | DHCPOPTION::Unknown(_) => DhcpOptionValue::Bytes(va.to_vec()),
         };
-        mac_addr.resize(16, 0);
-        let mut discover = DHCPDiscover::default();
-        discover.chaddr = mac_addr.try_into().unwrap();
-        discover
+        Ok(value)
     }
 
-    pub fn insert_options(&mut self, options: Vec<DHCPOPTIONS>) {
-        for ele in options.iter() {
-            self.options.push(ele.tp as u8);
-            self.options.push(ele.len);
-            self.options.extend(ele.va.clone());
-        }
-        self.options.push(0xff);
-        // if self.options.len() < 312 {
-        //     self.options.resize(312, 0);
-        // } else if self.options.len() > 576 {
-        //     panic!("DHCP Message must less then 512.")
-        // }
+    // None for values the option cannot carry, route prefixes longer than
+    // 32 bits and domain labels longer than 63 bytes
+    pub fn encode(&self) -> Option<Vec<u8>> {
+        let va = match self {
+            DhcpOptionValue::Address(addr) => addr.octets().to_vec(),
+            DhcpOptionValue::Addresses(addrs) => addrs.iter().flat_map(|a| a.octets()).collect(),
+            DhcpOptionValue::AddressPairs(pairs) => pairs
+                .iter()
+                .flat_map(|(a, b)| a.octets().into_iter().chain(b.octets()))
+                .collect(),
+            DhcpOptionValue::Duration(secs) => secs.to_be_bytes().to_vec(),
+            DhcpOptionValue::TimeOffset(offset) => offset.to_be_bytes().to_vec(),
+            DhcpOptionValue::U8(v) => vec![*v],
+            DhcpOptionValue::U16(v) => v.to_be_bytes().to_vec(),
+            DhcpOptionValue::U16List(vs) => vs.iter().flat_map(|v| v.to_be_bytes()).collect(),
+            DhcpOptionValue::String(s) => s.as_bytes().to_vec(),
+            DhcpOptionValue::Bool(b) => vec![*b as u8],
+            DhcpOptionValue::MessageType(tp) => vec![*tp as u8],
+            DhcpOptionValue::ParameterList(codes) => {
+                codes.iter().map(|&code| code.into()).collect()
+            }
+            DhcpOptionValue::ClientIdentifier { tp, id } => {
+                let mut va = vec![*tp];
+                va.extend_from_slice(id);
+                va
+            }
+            DhcpOptionValue::DomainList(domains) => encode_domain_list(domains)?,
+            DhcpOptionValue::ClasslessRoutes(routes) => {
+                let mut va = Vec::new();
+                for (destination, prefix, router) in routes {
+                    if *prefix > 32 {
+                        return None;
+                    }
+                    va.push(*prefix);
+                    va.extend_from_slice(&destination.octets()[..(*prefix as usize).div_ceil(8)]);
+                    va.extend_from_slice(&router.octets());
+                }
+                va
+            }
+            DhcpOptionValue::Bytes(va) => va.clone(),
+        };
+        Some(va)
     }
 }
 
-#[derive(Debug, Clone)]
-pub struct DHCPOffer {
-    pub op: DHCPOP,
-    pub htype: DHCPHType,
-    pub hlen: DHCPHLen,
-    pub hops: u8,
-    pub xid: u32,
-    pub secs: u16,
-    pub flags: u16,
-    pub ciaddr: [u8; 4],
-    pub yiaddr: [u8; 4],
-    pub siaddr: [u8; 4],
-    pub giaddr: [u8; 4],
-    pub chaddr: [u8; 16],
-    pub sname: [u8; 64],
-    pub file: [u8; 128],
-    pub options: Vec<DHCPOPTIONS>,
+// RFC 3397, names are encoded as in RFC 1035 and may use compression pointers
+// relative to the start of the option data
+fn decode_domain_list(va: &[u8]) -> Option<Vec<String>> {
+    let mut domains = Vec::new();
+    let mut pos = 0;
+    while pos < va.len() {
+        let mut labels: Vec<String> = Vec::new();
+        let mut cursor = pos;
+        let mut end = None;
+        // every jump lands before the name or the previous jump target, so
+        // a pointer loop cannot be followed forever
+        let mut limit = pos;
+        loop {
+            let len = *va.get(cursor)? as usize;
+            if len == 0 {
+                end.get_or_insert(cursor + 1);
+                break;
+            }
+            if len & 0xc0 == 0xc0 {
+                let pointer = ((len & 0x3f) << 8) | *va.get(cursor + 1)? as usize;
+                end.get_or_insert(cursor + 2);
+                if pointer >= limit {
+                    return None;
+                }
+                limit = pointer;
+                cursor = pointer;
+                continue;
+            }
+            let label = va.get(cursor + 1..cursor + 1 + len)?;
+            labels.push(String::from_utf8(label.to_vec()).ok()?);
+            cursor += 1 + len;
+        }
+        domains.push(labels.join("."));
+        pos = end?;
+    }
+    Some(domains)
 }
 
-impl DHCPOffer {
-    pub fn default() -> Self {
-        todo!()
+fn encode_domain_list(domains: &[String]) -> Option<Vec<u8>> {
+    let mut va = Vec::new();
+    for domain in domains {
+        for label in domain.split('.').filter(|label| !label.is_empty()) {
+            // longer lengths would read as a compression pointer
+            if label.len() > DNS_MAX_LABEL_LEN {
+                return None;
+            }
+            va.push(label.len() as u8);
+            va.extend_from_slice(label.as_bytes());
+        }
+        va.push(0);
     }
+    Some(va)
+}
 
-    pub fn from_bytes<T: Buf>(buf: &mut T) -> Self {
-        let op: DHCPOP = buf.get_u8().into();
-        let htype: DHCPHType = buf.get_u8().into();
-        let hlen = buf.get_u8();
-        let hops = buf.get_u8();
-        let xid = buf.get_u32();
-        let secs = buf.get_u16();
-        let flags = buf.get_u16();
-        let mut ciaddr = [0u8; 4];
-        let mut yiaddr = [0u8; 4];
-        let mut siaddr = [0u8; 4];
-        let mut giaddr = [0u8; 4];
-        for i in 0..4 {
-            ciaddr[i] = buf.get_u8();
+// RFC 3442, each route is the prefix length, the significant octets of the
+// destination and the router
+fn decode_classless_routes(mut va: &[u8]) -> Option<Vec<(Ipv4Addr, u8, Ipv4Addr)>> {
+    let mut routes = Vec::new();
+    while let Some((&prefix, rest)) = va.split_first() {
+        if prefix > 32 {
+            return None;
         }
-        for i in 0..4 {
-            yiaddr[i] = buf.get_u8();
+        let significant = (prefix as usize).div_ceil(8);
+        if rest.len() < significant + 4 {
+            return None;
         }
-        for i in 0..4 {
-            siaddr[i] = buf.get_u8();
+        let mut destination = [0u8; 4];
+        destination[..significant].copy_from_slice(&rest[..significant]);
+        let router: [u8; 4] = rest[significant..significant + 4].try_into().ok()?;
+        routes.push((Ipv4Addr::from(destination), prefix, Ipv4Addr::from(router)));
+        va = &rest[significant + 4..];
+    }
+    Some(routes)
+}
+
+fn parse_options(mut buf: &[u8], options: &mut Vec<DHCPOPTIONS>) -> Result<(), DHCPParseError> {
+    loop {
+        if !buf.has_remaining() {
+            return Err(DHCPParseError::MissingEnd);
         }
-        for i in 0..4 {
-            giaddr[i] = buf.get_u8();
+        let tp = buf.get_u8();
+        if tp == u8::from(DHCPOPTION::EndOption) {
+            return Ok(());
         }
-        let mut chaddr = [0u8; 16];
-        for i in 0..16 {
-            chaddr[i] = buf.get_u8();
+        if tp == u8::from(DHCPOPTION::PadOption) {
+            continue;
         }
-        let mut sname = [0u8; 64];
-        for i in 0..64 {
-            sname[i] = buf.get_u8();
+        if !buf.has_remaining() {
+            return Err(DHCPParseError::OptionOverrun(tp));
         }
-        let mut file = [0u8; 128];
-        for i in 0..128 {
-            file[i] = buf.get_u8();
+        let len = buf.get_u8();
+        if buf.remaining() < len as usize {
+            return Err(DHCPParseError::OptionOverrun(tp));
         }
-        // magic cookie dhcp read as u32 - [63, 82, 53, 63]
-        let magic_cookie_dhcp = buf.get_u32();
-        if magic_cookie_dhcp != 1669485411u32 {
-            println!("Error: {}", magic_cookie_dhcp);
-            // panic
-        }
-        let mut options: Vec<DHCPOPTIONS> = Vec::new();
-        loop {
-            let tp = buf.get_u8();
-            if tp == DHCPOPTION::EndOption as u8 {
-                break;
-            }
-            let len = buf.get_u8();
-            let mut va = Vec::with_capacity(len as usize);
-            for i in 0..len as usize {
-                va.push(buf.get_u8());
+        let mut va = vec![0u8; len as usize];
+        buf.copy_to_slice(&mut va);
+        options.push(DHCPOPTIONS::new(tp.into(), va))
+    }
+}
+
+// RFC 3396, repeated instances of an option are one value split in pieces
+fn concat_options(fragments: Vec<DHCPOPTIONS>) -> Vec<DHCPOPTIONS> {
+    let mut options: Vec<DHCPOPTIONS> = Vec::with_capacity(fragments.len());
+    for fragment in fragments {
+        match options.iter_mut().find(|option| option.tp == fragment.tp) {
+            Some(option) => {
+                option.va.extend_from_slice(&fragment.va);
+                option.len = option.va.len() as u16;
             }
-            options.push(DHCPOPTIONS {
-                tp: tp.into(),
-                len: len,
-                va: va,
-            })
-        }
-        DHCPOffer {
-            op: op,
-            htype: htype,
-            hlen: hlen.into(),
-            hops: hops,
-            xid: xid,
-            secs: secs,
-            flags: flags,
-            ciaddr: ciaddr,
-            yiaddr: yiaddr,
-            siaddr: siaddr,
-            giaddr: giaddr,
-            chaddr: chaddr,
-            sname: sname,
-            file: file,
-            options: options,
-        }
-    }
-}
-
-#[derive(Debug, Clone)]
-pub struct DHCPRequest {
+            None => options.push(fragment),
+        }
+    }
+    options
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DHCPMessage {
     pub op: DHCPOP,
     pub htype: DHCPHType,
     pub hlen: DHCPHLen,
@@ -433,20 +829,19 @@ pub struct DHCPRequest {
     pub xid: u32,
     pub secs: u16,
     pub flags: u16,
-    pub ciaddr: [u8; 4],
-    pub yiaddr: [u8; 4],
-    pub siaddr: [u8; 4],
-    pub giaddr: [u8; 4],
+    pub ciaddr: Ipv4Addr,
+    pub yiaddr: Ipv4Addr,
+    pub siaddr: Ipv4Addr,
+    pub giaddr: Ipv4Addr,
     pub chaddr: [u8; 16],
     pub sname: [u8; 64],
     pub file: [u8; 128],
-    // pub options: Vec<DHCPOPTIONS>,
-    pub options: Vec<u8>,
+    pub options: Vec<DHCPOPTIONS>,
 }
 
-impl Default for DHCPRequest {
+impl Default for DHCPMessage {
     fn default() -> Self {
-        DHCPRequest {
+        DHCPMessage {
             op: DHCPOP::BOOTREQUEST,
             htype: DHCPHType::ETHERNET,
             hlen: DHCPHLen::ETHERNET,
@@ -454,189 +849,310 @@ impl Default for DHCPRequest {
             xid: 0x00000000,
             secs: 0x0000,
             flags: 0x0000,
-            ciaddr: [0; 4],
-            yiaddr: [0; 4],
-            siaddr: [0; 4],
-            giaddr: [0; 4],
+            ciaddr: Ipv4Addr::UNSPECIFIED,
+            yiaddr: Ipv4Addr::UNSPECIFIED,
+            siaddr: Ipv4Addr::UNSPECIFIED,
+            giaddr: Ipv4Addr::UNSPECIFIED,
             chaddr: [0x00; 16],
             sname: [0; 64],
             file: [0; 128],
-            // options: Vec::new(),
-            options: vec![99, 130, 83, 99],
+            options: Vec::new(),
         }
     }
 }
 
-impl DHCPRequest {
-    //    fn with_mac_ip_options(mac: &str, ip: [u8; 4], options: Vec<DHCPOPTIONS>) -> Self {
-    pub fn with_mac_ip_options(mac: &str, options: Vec<DHCPOPTIONS>) -> Self {
-        let mut mac_addr: Vec<u8> = if mac.contains("-") {
-            str::split(mac, "-")
-                .map(|u| u8::from_str_radix(u, 16).unwrap())
-                .collect()
-        } else {
-            str::split(mac, ":")
-                .map(|u| u8::from_str_radix(u, 16).unwrap())
-                .collect()
-        };
-        mac_addr.resize(16, 0);
-        let mut dhcp_request = DHCPRequest::default();
-        dhcp_request.chaddr = mac_addr.try_into().unwrap();
-        // TODO: get_hostname()
-        // dhcp_request.sname =
-        for ele in options.into_iter() {
-            dhcp_request.options.push(ele.tp as u8);
-            dhcp_request.options.push(ele.len);
-            dhcp_request.options.extend(ele.va);
+impl DHCPMessage {
+    pub fn new(message_type: DHCPMessageType, mac: MacAddr) -> Self {
+        let mut message = DHCPMessage::with_mac(mac);
+        message.op = message_type.op();
+        message.set_message_type(message_type);
+        message
+    }
+
+    pub fn with_mac(mac: MacAddr) -> Self {
+        let mut chaddr = [0u8; 16];
+        chaddr[..6].copy_from_slice(&mac.octets());
+        DHCPMessage {
+            chaddr,
+            ..Default::default()
         }
-        dhcp_request.options.push(0xff);
-        dhcp_request
     }
-}
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
-pub struct DHCPDecline {}
+    pub fn message_type(&self) -> Option<DHCPMessageType> {
+        self.option(DHCPOPTION::DHCPMessageType)
+            .and_then(|option| option.va.first())
+            .and_then(|&tp| DHCPMessageType::try_from(tp).ok())
+    }
 
-#[derive(Debug, Clone)]
-pub struct DHCPAck {
-    pub op: DHCPOP,
-    pub htype: DHCPHType,
-    pub hlen: DHCPHLen,
-    pub hops: u8,
-    pub xid: u32,
-    pub secs: u16,
-    pub flags: u16,
-    pub ciaddr: [u8; 4],
-    pub yiaddr: [u8; 4],
-    pub siaddr: [u8; 4],
-    pub giaddr: [u8; 4],
-    pub chaddr: [u8; 16],
-    pub sname: [u8; 64],
-    pub file: [u8; 128],
-    pub options: Vec<DHCPOPTIONS>,
+    pub fn set_message_type(&mut self, message_type: DHCPMessageType) {
+        self.set_option(DHCPOPTIONS::new(
+            DHCPOPTION::DHCPMessageType,
+            vec![message_type as u8],
+        ));
+    }
+
+    pub fn option(&self, tp: DHCPOPTION) -> Option<&DHCPOPTIONS> {
+        self.options.iter().find(|option| option.tp == tp)
+    }
+
+    // replaces an existing option of the same type, keeping its position
+    pub fn set_option(&mut self, option: DHCPOPTIONS) {
+        match self.options.iter_mut().find(|ele| ele.tp == option.tp) {
+            Some(ele) => *ele = option,
+            None => self.options.push(option),
+        }
+    }
+
+    pub fn option_value(&self, tp: DHCPOPTION) -> Option<DhcpOptionValue> {
+        self.option(tp).and_then(|option| option.value().ok())
+    }
+
+    pub fn set_option_value(
+        &mut self,
+        tp: DHCPOPTION,
+        value: DhcpOptionValue,
+    ) -> Result<(), DHCPParseError> {
+        self.set_option(DHCPOPTIONS::from_value(tp, &value)?);
+        Ok(())
+    }
+
+    pub fn set_address_option(&mut self, tp: DHCPOPTION, addr: Ipv4Addr) {
+        self.set_option(DHCPOPTIONS::new(tp, addr.octets().to_vec()));
+    }
+
+    fn address_option(&self, tp: DHCPOPTION) -> Option<Ipv4Addr> {
+        match self.option_value(tp) {
+            Some(DhcpOptionValue::Address(addr)) => Some(addr),
+            _ => None,
+        }
+    }
+
+    fn addresses_option(&self, tp: DHCPOPTION) -> Vec<Ipv4Addr> {
+        match self.option_value(tp) {
+            Some(DhcpOptionValue::Addresses(addrs)) => addrs,
+            _ => Vec::new(),
+        }
+    }
+
+    fn duration_option(&self, tp: DHCPOPTION) -> Option<u32> {
+        match self.option_value(tp) {
+            Some(DhcpOptionValue::Duration(secs)) => Some(secs),
+            _ => None,
+        }
+    }
+
+    fn string_option(&self, tp: DHCPOPTION) -> Option<String> {
+        match self.option_value(tp) {
+            Some(DhcpOptionValue::String(s)) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn subnet_mask(&self) -> Option<Ipv4Addr> {
+        self.address_option(DHCPOPTION::SubnetMask)
+    }
+
+    pub fn server_identifier(&self) -> Option<Ipv4Addr> {
+        self.address_option(DHCPOPTION::ServerIdentifier)
+    }
+
+    pub fn requested_ip_address(&self) -> Option<Ipv4Addr> {
+        self.address_option(DHCPOPTION::RequestedIPAddress)
+    }
+
+    pub fn broadcast_address(&self) -> Option<Ipv4Addr> {
+        self.address_option(DHCPOPTION::BroadcastAddressOption)
+    }
+
+    pub fn routers(&self) -> Vec<Ipv4Addr> {
+        self.addresses_option(DHCPOPTION::RouterOption)
+    }
+
+    pub fn dns_servers(&self) -> Vec<Ipv4Addr> {
+        self.addresses_option(DHCPOPTION::DomainNameServerOption)
+    }
+
+    pub fn ntp_servers(&self) -> Vec<Ipv4Addr> {
+        self.addresses_option(DHCPOPTION::NetworkTimeProtocolServersOption)
+    }
+
+    // lease, T1 and T2 in seconds, 0xffffffff means infinity
+    pub fn lease_time(&self) -> Option<u32> {
+        self.duration_option(DHCPOPTION::IPAddressLeaseTime)
+    }
+
+    pub fn renewal_time(&self) -> Option<u32> {
+        self.duration_option(DHCPOPTION::RenewalTimeValue)
+    }
+
+    pub fn rebinding_time(&self) -> Option<u32> {
+        self.duration_option(DHCPOPTION::RebindingTimeValue)
+    }
+
+    pub fn interface_mtu(&self) -> Option<u16> {
+        match self.option_value(DHCPOPTION::InterfaceMTUOption) {
+            Some(DhcpOptionValue::U16(mtu)) => Some(mtu),
+            _ => None,
+        }
+    }
+
+    pub fn host_name(&self) -> Option<String> {
+        self.string_option(DHCPOPTION::HostNameOption)
+    }
+
+    pub fn domain_name(&self) -> Option<String> {
+        self.string_option(DHCPOPTION::DomainName)
+    }
+
+    pub fn domain_search(&self) -> Vec<String> {
+        match self.option_value(DHCPOPTION::DomainSearch) {
+            Some(DhcpOptionValue::DomainList(domains)) => domains,
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn classless_routes(&self) -> Vec<(Ipv4Addr, u8, Ipv4Addr)> {
+        match self.option_value(DHCPOPTION::ClasslessStaticRoute) {
+            Some(DhcpOptionValue::ClasslessRoutes(routes)) => routes,
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn server_message(&self) -> Option<String> {
+        self.string_option(DHCPOPTION::Message)
+    }
+
+    pub fn insert_options(&mut self, options: Vec<DHCPOPTIONS>) {
+        for ele in options.into_iter() {
+            self.set_option(ele);
+        }
+    }
 }
 
-impl DHCPAck {
-    pub fn from_bytes<T: Buf>(buf: &mut T) -> Self {
+impl TryFrom<&[u8]> for DHCPMessage {
+    type Error = DHCPParseError;
+
+    fn try_from(mut buf: &[u8]) -> Result<Self, Self::Error> {
+        if buf.len() < DHCP_HEADER_LEN + 4 {
+            return Err(DHCPParseError::TruncatedHeader(buf.len()));
+        }
         let op: DHCPOP = buf.get_u8().into();
         let htype: DHCPHType = buf.get_u8().into();
-        let hlen = buf.get_u8();
+        let hlen = DHCPHLen::try_from(buf.get_u8())?;
         let hops = buf.get_u8();
         let xid = buf.get_u32();
         let secs = buf.get_u16();
         let flags = buf.get_u16();
-        let mut ciaddr = [0u8; 4];
-        let mut yiaddr = [0u8; 4];
-        let mut siaddr = [0u8; 4];
-        let mut giaddr = [0u8; 4];
-        for i in 0..4 {
-            ciaddr[i] = buf.get_u8();
-        }
-        for i in 0..4 {
-            yiaddr[i] = buf.get_u8();
-        }
-        for i in 0..4 {
-            siaddr[i] = buf.get_u8();
-        }
-        for i in 0..4 {
-            giaddr[i] = buf.get_u8();
-        }
+        let ciaddr = Ipv4Addr::from(buf.get_u32());
+        let yiaddr = Ipv4Addr::from(buf.get_u32());
+        let siaddr = Ipv4Addr::from(buf.get_u32());
+        let giaddr = Ipv4Addr::from(buf.get_u32());
         let mut chaddr = [0u8; 16];
-        for i in 0..16 {
-            chaddr[i] = buf.get_u8();
-        }
+        buf.copy_to_slice(&mut chaddr);
         let mut sname = [0u8; 64];
-        for i in 0..64 {
-            sname[i] = buf.get_u8();
-        }
+        buf.copy_to_slice(&mut sname);
         let mut file = [0u8; 128];
-        for i in 0..128 {
-            file[i] = buf.get_u8();
-        }
-        // magic cookie dhcp read as u32 - [63, 82, 53, 63]
+        buf.copy_to_slice(&mut file);
         let magic_cookie_dhcp = buf.get_u32();
-        if magic_cookie_dhcp != 1669485411u32 {
-            println!("Error: {}", magic_cookie_dhcp);
-            // panic
+        if magic_cookie_dhcp != DHCP_MAGIC_COOKIE {
+            return Err(DHCPParseError::BadMagicCookie(magic_cookie_dhcp));
         }
         let mut options: Vec<DHCPOPTIONS> = Vec::new();
-        loop {
-            let tp = buf.get_u8();
-            if tp == DHCPOPTION::EndOption as u8 {
-                break;
+        parse_options(buf, &mut options)?;
+        // options continue in file and then sname, which no longer hold names
+        let overload = options
+            .iter()
+            .find(|option| option.tp == DHCPOPTION::OptionOverload)
+            .map(|option| option.va.first().copied().unwrap_or_default());
+        if let Some(overload) = overload {
+            options.retain(|option| option.tp != DHCPOPTION::OptionOverload);
+            if overload & DHCP_OVERLOAD_FILE != 0 {
+                parse_options(&file, &mut options)?;
+                file = [0; 128];
             }
-            let len = buf.get_u8();
-            let mut va = Vec::with_capacity(len as usize);
-            for i in 0..len as usize {
-                va.push(buf.get_u8());
+            if overload & DHCP_OVERLOAD_SNAME != 0 {
+                parse_options(&sname, &mut options)?;
+                sname = [0; 64];
             }
-            options.push(DHCPOPTIONS {
-                tp: tp.into(),
-                len: len,
-                va: va,
-            })
-        }
-        DHCPAck {
-            op: op,
-            htype: htype,
-            hlen: hlen.into(),
-            hops: hops,
-            xid: xid,
-            secs: secs,
-            flags: flags,
-            ciaddr: ciaddr,
-            yiaddr: yiaddr,
-            siaddr: siaddr,
-            giaddr: giaddr,
-            chaddr: chaddr,
-            sname: sname,
-            file: file,
-            options: options,
-        }
-    }
-}
-
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
-pub struct DHCPNak {}
-
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
-pub struct DHCPRelease {}
-
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
-pub struct DHCPInform {}
-
-impl DHCPBytes for DHCPDiscover {
-    fn to_bytes(&self) -> BytesMut {
-        let mut discover_bytes = BytesMut::new();
-        discover_bytes.put_u8(self.op as u8);
-        discover_bytes.put_u8(self.htype as u8);
-        discover_bytes.put_u8(self.hlen as u8);
-        discover_bytes.put_u8(self.hops);
-        discover_bytes.put_u32(self.xid);
-        discover_bytes.put_u16(self.secs);
-        discover_bytes.put_u16(self.flags);
-        discover_bytes.put_u32(self.ciaddr);
-        discover_bytes.put_u32(self.yiaddr);
-        discover_bytes.put_u32(self.siaddr);
-        discover_bytes.put_u32(self.giaddr);
-        for ele in self.chaddr.iter() {
-            discover_bytes.put_u8(*ele);
         }
-        for ele in self.sname.iter() {
-            discover_bytes.put_u8(*ele);
+        let options = concat_options(options);
+        Ok(DHCPMessage {
+            op,
+            htype,
+            hlen,
+            hops,
+            xid,
+            secs,
+            flags,
+            ciaddr,
+            yiaddr,
+            siaddr,
+            giaddr,
+            chaddr,
+            sname,
+            file,
+            options,
+        })
+    }
+}
+
+impl DHCPMessage {
+    pub fn max_message_size(&self) -> Option<u16> {
+        match self.option_value(DHCPOPTION::MaximumDHCPMessageSize) {
+            Some(DhcpOptionValue::U16(size)) => Some(size),
+            _ => None,
         }
-        for ele in self.file.iter() {
-            discover_bytes.put_u8(*ele);
+    }
+
+    // max_size is the negotiated IP datagram size, options which do not fit
+    // are moved into file and sname when those are not in use
+    pub fn to_bytes_with_max_size(&self, max_size: usize) -> BytesMut {
+        let encoded: Vec<Vec<u8>> = self
+            .options
+            .iter()
+            .filter(|option| option.tp != DHCPOPTION::OptionOverload)
+            .flat_map(encode_option)
+            .collect();
+        let space = max_size.saturating_sub(DHCP_IP_UDP_HEADER_LEN + DHCP_HEADER_LEN + 4 + 1);
+        let overloadable = self.file.iter().all(|&b| b == 0) && self.sname.iter().all(|&b| b == 0);
+        let mut fields: [Vec<u8>; 3] = Default::default();
+        if encoded.iter().map(Vec::len).sum::<usize>() <= space || !overloadable {
+            fields[0] = encoded.concat();
+        } else {
+            // main options (minus room for the overload option), file and sname,
+            // each followed by an end option
+            let capacities = [
+                space.saturating_sub(3),
+                self.file.len() - 1,
+                self.sname.len() - 1,
+            ];
+            let mut field = 0;
+            for ele in encoded {
+                while field < fields.len() && fields[field].len() + ele.len() > capacities[field] {
+                    field += 1;
+                }
+                // whatever is left overflows the main options field
+                let target = if field < fields.len() { field } else { 0 };
+                fields[target].extend_from_slice(&ele);
+            }
         }
-        for ele in self.options.iter() {
-            discover_bytes.put_u8(*ele);
+        let [main, file_options, sname_options] = fields;
+        let mut overload = 0u8;
+        let mut file = self.file;
+        let mut sname = self.sname;
+        if !file_options.is_empty() {
+            overload |= DHCP_OVERLOAD_FILE;
+            file = [0; 128];
+            file[..file_options.len()].copy_from_slice(&file_options);
+            file[file_options.len()] = DHCPOPTION::EndOption.into();
+        }
+        if !sname_options.is_empty() {
+            overload |= DHCP_OVERLOAD_SNAME;
+            sname = [0; 64];
+            sname[..sname_options.len()].copy_from_slice(&sname_options);
+            sname[sname_options.len()] = DHCPOPTION::EndOption.into();
         }
-        discover_bytes
-    }
-}
 
-impl DHCPBytes for DHCPRequest {
-    fn to_bytes(&self) -> BytesMut {
         let mut bytes = BytesMut::new();
         bytes.put_u8(self.op as u8);
         bytes.put_u8(self.htype as u8);
@@ -645,23 +1161,42 @@ impl DHCPBytes for DHCPRequest {
         bytes.put_u32(self.xid);
         bytes.put_u16(self.secs);
         bytes.put_u16(self.flags);
-        for addrs in [self.ciaddr, self.yiaddr, self.siaddr, self.giaddr] {
-            for addr in addrs {
-                bytes.put_u8(addr);
-            }
-        }
-        for ele in self.chaddr.iter() {
-            bytes.put_u8(*ele);
-        }
-        for ele in self.sname.iter() {
-            bytes.put_u8(*ele);
-        }
-        for ele in self.file.iter() {
-            bytes.put_u8(*ele);
+        for addr in [self.ciaddr, self.yiaddr, self.siaddr, self.giaddr] {
+            bytes.put_slice(&addr.octets());
         }
-        for ele in self.options.iter() {
-            bytes.put_u8(*ele);
+        bytes.put_slice(&self.chaddr);
+        bytes.put_slice(&sname);
+        bytes.put_slice(&file);
+        bytes.put_u32(DHCP_MAGIC_COOKIE);
+        if overload != 0 {
+            bytes.put_slice(&[DHCPOPTION::OptionOverload.into(), 1, overload]);
         }
+        bytes.put_slice(&main);
+        bytes.put_u8(DHCPOPTION::EndOption.into());
         bytes
     }
 }
+
+// values longer than 255 bytes are split into several instances of the option
+fn encode_option(option: &DHCPOPTIONS) -> Vec<Vec<u8>> {
+    if option.va.is_empty() {
+        return vec![vec![option.tp.into(), 0]];
+    }
+    option
+        .va
+        .chunks(u8::MAX as usize)
+        .map(|chunk| {
+            let mut bytes = Vec::with_capacity(chunk.len() + 2);
+            bytes.push(option.tp.into());
+            bytes.push(chunk.len() as u8);
+            bytes.extend_from_slice(chunk);
+            bytes
+        })
+        .collect()
+}
+
+impl DHCPBytes for DHCPMessage {
+    fn to_bytes(&self) -> BytesMut {
+        self.to_bytes_with_max_size(DHCP_MIN_MESSAGE_SIZE)
+    }
+}
@@ -0,0 +1,1293 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::net::{Ipv4Addr, SocketAddrV4};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use pnet::datalink::{self, NetworkInterface};
+use pnet::util::{MacAddr, ParseMacAddrErr};
+use rand::Rng;
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::BroadcastStream;
+
+use super::hook::{run_hook, DhcpHookReason};
+use super::lease::DhcpLeaseRecord;
+use super::resolver::{DhcpDnsSettings, DhcpResolver, DhcpResolverConfig};
+use super::transport::DhcpTransport;
+use super::*;
+use crate::arp;
+
+// println! tagged with the client's interface, so the logs of clients
+// running side by side can be told apart
+macro_rules! log {
+    ($client:expr, $($arg:tt)*) => {
+        match &$client.config.interface {
+            Some(interface) => println!("[{}] {}", interface, format_args!($($arg)*)),
+            None => println!($($arg)*),
+        }
+    };
+}
+
+pub const DHCP_SERVER_PORT: u16 = 67;
+pub const DHCP_CLIENT_PORT: u16 = 68;
+
+// RFC 2131 4.4 client states, INIT-REBOOT only lasts while start sends the
+// REQUEST and is not kept
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DhcpState {
+    Init,
+    Selecting,
+    Requesting,
+    Bound,
+    Renewing,
+    Rebinding,
+    Rebooting,
+    // DHCPINFORM sent for an externally configured address, not part of
+    // the RFC 2131 lease state machine
+    Informing,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DhcpDestination {
+    Broadcast,
+    Unicast(Ipv4Addr),
+}
+
+impl DhcpDestination {
+    pub fn addr(&self) -> SocketAddrV4 {
+        match self {
+            DhcpDestination::Broadcast => SocketAddrV4::new(Ipv4Addr::BROADCAST, DHCP_SERVER_PORT),
+            DhcpDestination::Unicast(addr) => SocketAddrV4::new(*addr, DHCP_SERVER_PORT),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DhcpClientConfig {
+    pub mac: MacAddr,
+    pub client_identifier: DhcpOptionValue,
+    pub parameter_request_list: Vec<DHCPOPTION>,
+    pub host_name: Option<String>,
+    // start from INIT-REBOOT asking for this address
+    pub requested_address: Option<Ipv4Addr>,
+    // retransmissions after the first message of an exchange
+    pub max_retransmits: u32,
+    pub interface: Option<String>,
+    // ARP probe a new address before using it, see arp::probe
+    pub probe_address: bool,
+    // where the current lease is kept, see lease::DhcpLeaseRecord
+    pub lease_file: Option<PathBuf>,
+    // how long to collect offers after the first one, zero takes the first
+    pub offer_window: Duration,
+    pub offer_policy: DhcpOfferPolicy,
+    // put the lease on the interface, see netlink::DhcpNetlink
+    pub configure_interface: bool,
+    // where the lease's DNS servers and search domains go
+    pub resolver: Option<DhcpResolverConfig>,
+    // executable run on lease changes like dhclient-script, see hook::run_hook
+    pub hook: Option<PathBuf>,
+}
+
+impl DhcpClientConfig {
+    pub fn new(mac: MacAddr) -> Self {
+        DhcpClientConfig {
+            mac,
+            client_identifier: DhcpOptionValue::ClientIdentifier {
+                tp: DHCPHType::ETHERNET as u8,
+                id: mac.octets().to_vec(),
+            },
+            parameter_request_list: vec![
+                DHCPOPTION::SubnetMask,
+                DHCPOPTION::RouterOption,
+                DHCPOPTION::DomainNameServerOption,
+                DHCPOPTION::DomainName,
+                DHCPOPTION::NetworkTimeProtocolServersOption,
+                DHCPOPTION::IPAddressLeaseTime,
+                DHCPOPTION::RenewalTimeValue,
+                DHCPOPTION::RebindingTimeValue,
+            ],
+            host_name: None,
+            requested_address: None,
+            max_retransmits: 4,
+            interface: None,
+            probe_address: true,
+            lease_file: None,
+            offer_window: Duration::ZERO,
+            offer_policy: DhcpOfferPolicy::default(),
+            configure_interface: false,
+            resolver: None,
+            hook: None,
+        }
+    }
+
+    // a client on interface using its hardware address
+    pub fn for_interface(interface: &NetworkInterface) -> io::Result<Self> {
+        let mut config = DhcpClientConfig::new(interface_mac(interface)?);
+        config.interface = Some(interface.name.clone());
+        Ok(config)
+    }
+}
+
+pub fn find_interface(name: &str) -> io::Result<NetworkInterface> {
+    datalink::interfaces()
+        .into_iter()
+        .find(|interface| interface.name == name)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, name.to_string()))
+}
+
+pub fn interface_mac(interface: &NetworkInterface) -> io::Result<MacAddr> {
+    match interface.mac {
+        Some(mac) if !mac.is_zero() => Ok(mac),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} has no hardware address", interface.name),
+        )),
+    }
+}
+
+// a client hardware address given as aa:bb:cc:dd:ee:ff or AA-BB-CC-DD-EE-FF,
+// which has to be a unicast one
+pub fn parse_mac(mac: &str) -> io::Result<MacAddr> {
+    let invalid = |reason: String| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid MAC address {}: {}", mac, reason),
+        )
+    };
+    let parsed: MacAddr = mac
+        .replace('-', ":")
+        .parse()
+        .map_err(|e: ParseMacAddrErr| invalid(e.to_string()))?;
+    if parsed.is_zero() || !parsed.is_unicast() {
+        return Err(invalid("not a unicast address".to_string()));
+    }
+    Ok(parsed)
+}
+
+// how one offer is chosen among those collected in the offer window, the
+// criteria are applied in the order of the fields
+#[derive(Debug, Clone, Default)]
+pub struct DhcpOfferPolicy {
+    // servers whose offers are never taken
+    pub rejected_servers: Vec<Ipv4Addr>,
+    // servers whose offers win over any other
+    pub preferred_servers: Vec<Ipv4Addr>,
+    // an offer of the address held before wins over the remaining ones
+    pub prefer_previous_address: bool,
+    // otherwise take the longest lease rather than the first offer
+    pub prefer_longest_lease: bool,
+}
+
+impl DhcpOfferPolicy {
+    pub fn rejects(&self, offer: &DHCPMessage) -> bool {
+        offer
+            .server_identifier()
+            .is_some_and(|server| self.rejected_servers.contains(&server))
+    }
+
+    // offers compare by rank, the highest one is selected
+    fn rank(&self, offer: &DHCPMessage, previous: Option<Ipv4Addr>) -> (bool, bool, u32) {
+        let preferred = offer
+            .server_identifier()
+            .is_some_and(|server| self.preferred_servers.contains(&server));
+        let held = self.prefer_previous_address && previous == Some(offer.yiaddr);
+        let lease_time = if self.prefer_longest_lease {
+            offer.lease_time().unwrap_or_default()
+        } else {
+            0
+        };
+        (preferred, held, lease_time)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DhcpOfferRejection {
+    // the server is on DhcpOfferPolicy::rejected_servers
+    RejectedServer,
+    // another offer ranked higher
+    NotSelected,
+}
+
+// RENEWING and REBINDING retransmit after half the remaining time, but not
+// more often than this
+pub const DHCP_MIN_RETRANSMIT_SECS: u64 = 60;
+// RFC 2131 4.1, SELECTING, REQUESTING and REBOOTING wait 4, 8, 16 ... 64
+// seconds randomized by +/- 1 second between transmissions
+pub const DHCP_INITIAL_BACKOFF_SECS: u64 = 4;
+pub const DHCP_MAX_BACKOFF_SECS: u64 = 64;
+// pause before discovering again after a DHCPNAK, so a server that keeps
+// refusing us is not flooded
+pub const DHCP_RESTART_DELAY_SECS: u64 = 10;
+// MaximumDHCPMessageSize we send, the size of our receive buffer
+pub const DHCP_MAX_MESSAGE_SIZE: u16 = 1500;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DhcpLease {
+    pub address: Ipv4Addr,
+    pub server_identifier: Ipv4Addr,
+    // seconds from acquired, u32::MAX means infinity
+    pub lease_time: u32,
+    pub renewal_time: u32,
+    pub rebinding_time: u32,
+    pub acquired: Instant,
+    pub options: Vec<DHCPOPTIONS>,
+}
+
+impl DhcpLease {
+    pub fn from_ack(ack: &DHCPMessage, acquired: Instant) -> Option<Self> {
+        let lease_time = ack.lease_time()?;
+        // RFC 2131 4.4.5 defaults, also used when the server sends T1/T2
+        // that are out of order
+        let mut renewal_time = ack.renewal_time().unwrap_or(lease_time / 2);
+        let mut rebinding_time = ack
+            .rebinding_time()
+            .unwrap_or((lease_time as u64 * 7 / 8) as u32);
+        if lease_time != u32::MAX
+            && !(renewal_time <= rebinding_time && rebinding_time <= lease_time)
+        {
+            renewal_time = lease_time / 2;
+            rebinding_time = (lease_time as u64 * 7 / 8) as u32;
+        }
+        Some(DhcpLease {
+            address: ack.yiaddr,
+            server_identifier: ack.server_identifier()?,
+            lease_time,
+            renewal_time,
+            rebinding_time,
+            acquired,
+            options: ack.options.clone(),
+        })
+    }
+
+    pub fn is_infinite(&self) -> bool {
+        self.lease_time == u32::MAX
+    }
+
+    fn after(&self, secs: u32) -> Option<Instant> {
+        if self.is_infinite() {
+            return None;
+        }
+        Some(self.acquired + Duration::from_secs(secs as u64))
+    }
+
+    // T1, when RENEWING starts
+    pub fn renew_at(&self) -> Option<Instant> {
+        self.after(self.renewal_time)
+    }
+
+    // T2, when REBINDING starts
+    pub fn rebind_at(&self) -> Option<Instant> {
+        self.after(self.rebinding_time)
+    }
+
+    pub fn expires_at(&self) -> Option<Instant> {
+        self.after(self.lease_time)
+    }
+
+    // the options of the lease with DHCPMessage's typed accessors
+    pub fn parameters(&self) -> DHCPMessage {
+        DHCPMessage {
+            yiaddr: self.address,
+            options: self.options.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+impl fmt::Display for DhcpLease {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} from {}", self.address, self.server_identifier)?;
+        match (self.renew_at(), self.rebind_at(), self.expires_at()) {
+            (Some(t1), Some(t2), Some(end)) => {
+                let now = Instant::now();
+                write!(
+                    f,
+                    ", renew in {:?}, rebind in {:?}, expires in {:?}",
+                    t1.saturating_duration_since(now),
+                    t2.saturating_duration_since(now),
+                    end.saturating_duration_since(now),
+                )
+            }
+            _ => write!(f, ", infinite"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DhcpAction {
+    Send(Box<DHCPMessage>, DhcpDestination),
+    Bound(DhcpLease),
+    Renewed(DhcpLease),
+    Rebound(DhcpLease),
+    Expired(DhcpLease),
+    // the server refused the address, the lease (if any) must be unconfigured
+    Nak {
+        message: Option<String>,
+        lease: Option<DhcpLease>,
+    },
+    // check that nobody else uses the address, answered with handle_probe
+    Probe(Ipv4Addr),
+    // the acknowledged address is in use and was declined
+    Declined(DhcpLease),
+    Released(DhcpLease),
+    // an offer that was not taken, for logging
+    OfferRejected(Box<DHCPMessage>, DhcpOfferRejection),
+    // DHCPACK answering a DHCPINFORM, carrying configuration but no lease
+    Informed(Box<DHCPMessage>),
+    // no server answered in this state after every retransmission
+    TimedOut(DhcpState),
+}
+
+// What DhcpClient::subscribe receivers see while the client runs.
+#[derive(Debug, Clone)]
+pub enum DhcpEvent {
+    // an offer for the current DHCPDISCOVER, before offer selection
+    OfferReceived(Box<DHCPMessage>),
+    Bound(DhcpLease),
+    Renewed(DhcpLease),
+    Rebound(DhcpLease),
+    Nak {
+        message: Option<String>,
+        lease: Option<DhcpLease>,
+    },
+    Expired(DhcpLease),
+    Released(DhcpLease),
+    // the transport failed and the client stopped
+    TransportError(Arc<io::Error>),
+}
+
+// events a slow subscriber may fall behind before it misses some
+pub const DHCP_EVENT_CAPACITY: usize = 64;
+
+#[derive(Debug)]
+pub enum DhcpClientError {
+    Io(io::Error),
+    Timeout { state: DhcpState, attempts: u32 },
+}
+
+impl fmt::Display for DhcpClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DhcpClientError::Io(e) => write!(f, "DHCP transport error: {}", e),
+            DhcpClientError::Timeout { state, attempts } => write!(
+                f,
+                "no DHCP reply in {:?} after {} transmissions",
+                state, attempts
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DhcpClientError {}
+
+impl From<io::Error> for DhcpClientError {
+    fn from(value: io::Error) -> Self {
+        DhcpClientError::Io(value)
+    }
+}
+
+// Sans-IO RFC 2131 client, fed with received messages and timeouts and
+// answering with the actions to carry out.
+#[derive(Debug, Clone)]
+pub struct DhcpClient {
+    config: DhcpClientConfig,
+    state: DhcpState,
+    chaddr: [u8; 16],
+    xid: u32,
+    started: Instant,
+    deadline: Option<Instant>,
+    // last message of the exchange and how often it was retransmitted
+    pending: Option<(DHCPMessage, DhcpDestination)>,
+    attempts: u32,
+    // acknowledged but not yet probed
+    tentative: Option<DhcpLease>,
+    lease: Option<DhcpLease>,
+    // offers collected in SELECTING until the offer window closes
+    offers: Vec<DHCPMessage>,
+    // last address we held, see DhcpOfferPolicy::prefer_previous_address
+    previous: Option<Ipv4Addr>,
+    // lease last handed to the hook, its old_* variables
+    reported: Option<DhcpLease>,
+    // MTU of the interface before configure changed it
+    mtu: Option<u32>,
+    // lease file of a previous run asked for in INIT-REBOOT, dropped along
+    // with its configuration when the server NAKs it
+    rebooting: Option<DhcpLeaseRecord>,
+    events: broadcast::Sender<DhcpEvent>,
+}
+
+impl DhcpClient {
+    pub fn new(config: DhcpClientConfig) -> Self {
+        DhcpClient {
+            chaddr: DHCPMessage::with_mac(config.mac).chaddr,
+            config,
+            state: DhcpState::Init,
+            xid: rand::random(),
+            started: Instant::now(),
+            deadline: None,
+            pending: None,
+            attempts: 0,
+            tentative: None,
+            lease: None,
+            offers: Vec::new(),
+            previous: None,
+            reported: None,
+            mtu: None,
+            rebooting: None,
+            events: broadcast::channel(DHCP_EVENT_CAPACITY).0,
+        }
+    }
+
+    pub fn state(&self) -> DhcpState {
+        self.state
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<DhcpEvent> {
+        self.events.subscribe()
+    }
+
+    // subscribe as a Stream, lagging behind shows up as an error item
+    pub fn event_stream(&self) -> BroadcastStream<DhcpEvent> {
+        BroadcastStream::new(self.subscribe())
+    }
+
+    fn emit(&self, event: DhcpEvent) {
+        // nobody listening is fine
+        let _ = self.events.send(event);
+    }
+
+    pub fn lease(&self) -> Option<&DhcpLease> {
+        self.lease.as_ref()
+    }
+
+    // when handle_timeout should be called next
+    pub fn timeout(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    pub fn start(&mut self, now: Instant) -> Vec<DhcpAction> {
+        if self.config.requested_address.is_some() {
+            self.previous = self.config.requested_address;
+        }
+        match self.config.requested_address {
+            Some(addr) => self.init_reboot(addr, now),
+            None => self.init(now),
+        }
+    }
+
+    // whether message answers our current exchange, other clients on the same
+    // broadcast domain see the same replies
+    pub fn accepts(&self, message: &DHCPMessage) -> bool {
+        message.op == DHCPOP::BOOTREPLY && message.xid == self.xid && message.chaddr == self.chaddr
+    }
+
+    pub fn handle_message(&mut self, message: DHCPMessage, now: Instant) -> Vec<DhcpAction> {
+        if !self.accepts(&message) {
+            return Vec::new();
+        }
+        match (self.state, message.message_type()) {
+            (DhcpState::Selecting, Some(DHCPMessageType::DHCPOFFER)) => self.offer(message, now),
+            (DhcpState::Informing, Some(DHCPMessageType::DHCPACK)) => {
+                self.state = DhcpState::Init;
+                self.pending = None;
+                self.deadline = None;
+                vec![DhcpAction::Informed(Box::new(message))]
+            }
+            (
+                DhcpState::Requesting
+                | DhcpState::Rebooting
+                | DhcpState::Renewing
+                | DhcpState::Rebinding,
+                Some(DHCPMessageType::DHCPACK),
+            ) => self.bind(message),
+            (
+                DhcpState::Requesting
+                | DhcpState::Rebooting
+                | DhcpState::Renewing
+                | DhcpState::Rebinding,
+                Some(DHCPMessageType::DHCPNAK),
+            ) => self.nak(message, now),
+            _ => Vec::new(),
+        }
+    }
+
+    // asks for configuration only, for hosts with a static address
+    pub fn start_inform(&mut self, addr: Ipv4Addr, now: Instant) -> Vec<DhcpAction> {
+        self.new_exchange(now);
+        let mut inform = self.message(DHCPMessageType::DHCPINFORM, now);
+        inform.ciaddr = addr;
+        self.state = DhcpState::Informing;
+        self.transmit(inform, DhcpDestination::Broadcast, now)
+    }
+
+    // continue with a lease acquired earlier, e.g. to release it
+    pub fn restore(&mut self, lease: DhcpLease) {
+        self.state = DhcpState::Bound;
+        self.pending = None;
+        self.deadline = lease.renew_at();
+        self.previous = Some(lease.address);
+        self.lease = Some(lease);
+    }
+
+    // restore with what was stored along with the lease, so unconfigure
+    // puts back the MTU
+    pub fn restore_record(&mut self, record: &DhcpLeaseRecord) {
+        self.restore(record.to_lease());
+        self.mtu = record.mtu;
+    }
+
+    // gives the lease back to the server, the client stays idle in INIT
+    pub fn release(&mut self, now: Instant) -> Vec<DhcpAction> {
+        self.state = DhcpState::Init;
+        self.pending = None;
+        self.tentative = None;
+        self.deadline = None;
+        let Some(lease) = self.lease.take() else {
+            return Vec::new();
+        };
+        self.new_exchange(now);
+        let mut release = self.message(DHCPMessageType::DHCPRELEASE, now);
+        // RFC 2131 table 5, nothing is requested and no reply is sent
+        release.options.retain(|option| {
+            option.tp != DHCPOPTION::ParameterRequestList
+                && option.tp != DHCPOPTION::MaximumDHCPMessageSize
+        });
+        release.ciaddr = lease.address;
+        release.set_address_option(DHCPOPTION::ServerIdentifier, lease.server_identifier);
+        vec![
+            DhcpAction::Send(
+                Box::new(release),
+                DhcpDestination::Unicast(lease.server_identifier),
+            ),
+            DhcpAction::Released(lease),
+        ]
+    }
+
+    pub fn handle_probe(&mut self, conflict: bool, now: Instant) -> Vec<DhcpAction> {
+        // a NAK or a restart while probing gave the address up already
+        if !matches!(self.state, DhcpState::Requesting | DhcpState::Rebooting) {
+            return Vec::new();
+        }
+        let Some(lease) = self.tentative.take() else {
+            return Vec::new();
+        };
+        if conflict {
+            return self.decline(lease, now);
+        }
+        self.state = DhcpState::Bound;
+        self.deadline = lease.renew_at();
+        self.lease = Some(lease.clone());
+        vec![DhcpAction::Bound(lease)]
+    }
+
+    pub fn handle_timeout(&mut self, now: Instant) -> Vec<DhcpAction> {
+        match self.deadline {
+            Some(deadline) if deadline <= now => {}
+            _ => return Vec::new(),
+        }
+        match self.state {
+            DhcpState::Init => return self.init(now),
+            DhcpState::Selecting if !self.offers.is_empty() => return self.choose(now),
+            DhcpState::Selecting
+            | DhcpState::Requesting
+            | DhcpState::Rebooting
+            | DhcpState::Informing => return self.retransmit(now),
+            _ => {}
+        }
+        let Some(lease) = self.lease.clone() else {
+            self.deadline = None;
+            return Vec::new();
+        };
+        match self.state {
+            DhcpState::Bound => {
+                self.state = DhcpState::Renewing;
+                self.new_exchange(now);
+                self.renew(&lease, now)
+            }
+            DhcpState::Renewing if lease.rebind_at().is_some_and(|t2| now < t2) => {
+                self.renew(&lease, now)
+            }
+            DhcpState::Renewing => {
+                self.state = DhcpState::Rebinding;
+                self.rebind(&lease, now)
+            }
+            DhcpState::Rebinding if lease.expires_at().is_some_and(|end| now < end) => {
+                self.rebind(&lease, now)
+            }
+            DhcpState::Rebinding => {
+                self.lease = None;
+                let mut actions = vec![DhcpAction::Expired(lease)];
+                actions.extend(self.init(now));
+                actions
+            }
+            _ => {
+                self.deadline = None;
+                Vec::new()
+            }
+        }
+    }
+
+    fn renew(&mut self, lease: &DhcpLease, now: Instant) -> Vec<DhcpAction> {
+        self.deadline = retransmit_at(now, lease.rebind_at());
+        vec![DhcpAction::Send(
+            Box::new(self.request_extending(lease, now)),
+            DhcpDestination::Unicast(lease.server_identifier),
+        )]
+    }
+
+    fn rebind(&mut self, lease: &DhcpLease, now: Instant) -> Vec<DhcpAction> {
+        self.deadline = retransmit_at(now, lease.expires_at());
+        vec![DhcpAction::Send(
+            Box::new(self.request_extending(lease, now)),
+            DhcpDestination::Broadcast,
+        )]
+    }
+
+    fn transmit(
+        &mut self,
+        message: DHCPMessage,
+        destination: DhcpDestination,
+        now: Instant,
+    ) -> Vec<DhcpAction> {
+        self.attempts = 0;
+        self.deadline = Some(now + backoff(0));
+        self.pending = Some((message.clone(), destination));
+        vec![DhcpAction::Send(Box::new(message), destination)]
+    }
+
+    fn retransmit(&mut self, now: Instant) -> Vec<DhcpAction> {
+        let Some((mut message, destination)) = self.pending.take() else {
+            self.deadline = None;
+            return Vec::new();
+        };
+        if self.attempts >= self.config.max_retransmits {
+            self.deadline = None;
+            return match self.state {
+                // RFC 2131 3.1, without an answer to the REQUEST start over
+                DhcpState::Requesting | DhcpState::Rebooting => self.init(now),
+                state => vec![DhcpAction::TimedOut(state)],
+            };
+        }
+        self.attempts += 1;
+        message.secs = self.secs(now);
+        self.deadline = Some(now + backoff(self.attempts));
+        self.pending = Some((message.clone(), destination));
+        vec![DhcpAction::Send(Box::new(message), destination)]
+    }
+
+    fn nak(&mut self, nak: DHCPMessage, now: Instant) -> Vec<DhcpAction> {
+        self.state = DhcpState::Init;
+        self.pending = None;
+        self.tentative = None;
+        self.deadline = Some(now + Duration::from_secs(DHCP_RESTART_DELAY_SECS));
+        vec![DhcpAction::Nak {
+            message: nak.server_message(),
+            lease: self.lease.take(),
+        }]
+    }
+
+    fn init(&mut self, now: Instant) -> Vec<DhcpAction> {
+        self.new_exchange(now);
+        self.tentative = None;
+        let discover = self.message(DHCPMessageType::DHCPDISCOVER, now);
+        self.state = DhcpState::Selecting;
+        self.transmit(discover, DhcpDestination::Broadcast, now)
+    }
+
+    fn init_reboot(&mut self, addr: Ipv4Addr, now: Instant) -> Vec<DhcpAction> {
+        self.new_exchange(now);
+        let mut request = self.message(DHCPMessageType::DHCPREQUEST, now);
+        request.set_address_option(DHCPOPTION::RequestedIPAddress, addr);
+        self.state = DhcpState::Rebooting;
+        self.transmit(request, DhcpDestination::Broadcast, now)
+    }
+
+    fn offer(&mut self, offer: DHCPMessage, now: Instant) -> Vec<DhcpAction> {
+        if offer.server_identifier().is_none() {
+            return Vec::new();
+        }
+        if self.config.offer_policy.rejects(&offer) {
+            return vec![DhcpAction::OfferRejected(
+                Box::new(offer),
+                DhcpOfferRejection::RejectedServer,
+            )];
+        }
+        if self.config.offer_window.is_zero() {
+            return self.select(offer, now);
+        }
+        // the first offer stops retransmitting DHCPDISCOVER and opens the window
+        if self.offers.is_empty() {
+            self.pending = None;
+            self.deadline = Some(now + self.config.offer_window);
+        }
+        self.offers.push(offer);
+        Vec::new()
+    }
+
+    fn choose(&mut self, now: Instant) -> Vec<DhcpAction> {
+        let mut offers = std::mem::take(&mut self.offers).into_iter();
+        let Some(mut best) = offers.next() else {
+            return Vec::new();
+        };
+        let policy = &self.config.offer_policy;
+        let mut actions = Vec::new();
+        for offer in offers {
+            // ties keep the earlier offer
+            let rejected = if policy.rank(&offer, self.previous) > policy.rank(&best, self.previous)
+            {
+                std::mem::replace(&mut best, offer)
+            } else {
+                offer
+            };
+            actions.push(DhcpAction::OfferRejected(
+                Box::new(rejected),
+                DhcpOfferRejection::NotSelected,
+            ));
+        }
+        actions.extend(self.select(best, now));
+        actions
+    }
+
+    fn select(&mut self, offer: DHCPMessage, now: Instant) -> Vec<DhcpAction> {
+        let Some(server_identifier) = offer.server_identifier() else {
+            return Vec::new();
+        };
+        let mut request = self.message(DHCPMessageType::DHCPREQUEST, now);
+        request.set_address_option(DHCPOPTION::ServerIdentifier, server_identifier);
+        request.set_address_option(DHCPOPTION::RequestedIPAddress, offer.yiaddr);
+        self.state = DhcpState::Requesting;
+        self.transmit(request, DhcpDestination::Broadcast, now)
+    }
+
+    fn bind(&mut self, ack: DHCPMessage) -> Vec<DhcpAction> {
+        // the lease starts when the exchange started, not when the ACK arrived
+        let Some(lease) = DhcpLease::from_ack(&ack, self.started) else {
+            return Vec::new();
+        };
+        if self.tentative.is_some() {
+            return Vec::new();
+        }
+        self.pending = None;
+        match self.state {
+            DhcpState::Requesting | DhcpState::Rebooting if self.config.probe_address => {
+                self.deadline = None;
+                let address = lease.address;
+                self.tentative = Some(lease);
+                return vec![DhcpAction::Probe(address)];
+            }
+            _ => {}
+        }
+        let previous = std::mem::replace(&mut self.state, DhcpState::Bound);
+        self.deadline = lease.renew_at();
+        self.previous = Some(lease.address);
+        self.lease = Some(lease.clone());
+        match previous {
+            DhcpState::Renewing => vec![DhcpAction::Renewed(lease)],
+            DhcpState::Rebinding => vec![DhcpAction::Rebound(lease)],
+            _ => vec![DhcpAction::Bound(lease)],
+        }
+    }
+
+    // RFC 2131 3.1, DECLINE the address and wait before discovering again
+    fn decline(&mut self, lease: DhcpLease, now: Instant) -> Vec<DhcpAction> {
+        let mut decline = self.message(DHCPMessageType::DHCPDECLINE, now);
+        decline.options.retain(|option| {
+            option.tp != DHCPOPTION::ParameterRequestList
+                && option.tp != DHCPOPTION::MaximumDHCPMessageSize
+        });
+        decline.set_address_option(DHCPOPTION::RequestedIPAddress, lease.address);
+        decline.set_address_option(DHCPOPTION::ServerIdentifier, lease.server_identifier);
+        self.state = DhcpState::Init;
+        self.deadline = Some(now + Duration::from_secs(DHCP_RESTART_DELAY_SECS));
+        vec![
+            DhcpAction::Send(Box::new(decline), DhcpDestination::Broadcast),
+            DhcpAction::Declined(lease),
+        ]
+    }
+
+    // RENEWING and REBINDING requests carry the leased address in ciaddr
+    fn request_extending(&self, lease: &DhcpLease, now: Instant) -> DHCPMessage {
+        let mut request = self.message(DHCPMessageType::DHCPREQUEST, now);
+        request.ciaddr = lease.address;
+        request
+    }
+
+    fn new_exchange(&mut self, now: Instant) {
+        self.offers.clear();
+        self.xid = rand::random();
+        self.started = now;
+    }
+
+    fn secs(&self, now: Instant) -> u16 {
+        now.duration_since(self.started)
+            .as_secs()
+            .min(u16::MAX as u64) as u16
+    }
+
+    fn message(&self, message_type: DHCPMessageType, now: Instant) -> DHCPMessage {
+        let mut message = DHCPMessage::new(message_type, self.config.mac);
+        message.xid = self.xid;
+        message.secs = self.secs(now);
+        let mut options = vec![
+            (
+                DHCPOPTION::ClientIdentifier,
+                self.config.client_identifier.clone(),
+            ),
+            (
+                DHCPOPTION::ParameterRequestList,
+                DhcpOptionValue::ParameterList(self.config.parameter_request_list.clone()),
+            ),
+            (
+                DHCPOPTION::MaximumDHCPMessageSize,
+                DhcpOptionValue::U16(DHCP_MAX_MESSAGE_SIZE),
+            ),
+        ];
+        if let Some(host_name) = &self.config.host_name {
+            options.push((
+                DHCPOPTION::HostNameOption,
+                DhcpOptionValue::String(host_name.clone()),
+            ));
+        }
+        for (tp, value) in options {
+            // only domain lists and classless routes can be out of range
+            message
+                .set_option_value(tp, value)
+                .expect("client options always encode");
+        }
+        message
+    }
+
+    // drives the client over transport until shutdown completes, then
+    // releases the lease
+    pub async fn run<T: DhcpTransport, F: Future>(
+        &mut self,
+        transport: &T,
+        shutdown: F,
+    ) -> Result<(), DhcpClientError> {
+        let result = self.drive(transport, shutdown).await;
+        if let Err(DhcpClientError::Io(e)) = &result {
+            let e = io::Error::new(e.kind(), e.to_string());
+            self.emit(DhcpEvent::TransportError(Arc::new(e)));
+        }
+        result
+    }
+
+    async fn drive<T: DhcpTransport, F: Future>(
+        &mut self,
+        transport: &T,
+        shutdown: F,
+    ) -> Result<(), DhcpClientError> {
+        tokio::pin!(shutdown);
+        if self.config.requested_address.is_none() {
+            self.rebooting = self.load_lease();
+            self.config.requested_address = self.rebooting.as_ref().map(|record| record.address);
+        }
+        let actions = self.start(Instant::now());
+        self.perform(transport, actions).await?;
+        let mut buf = [0u8; DHCP_MAX_MESSAGE_SIZE as usize];
+        loop {
+            let deadline = self.timeout();
+            let received = tokio::select! {
+                _ = &mut shutdown => return self.release_lease(transport).await,
+                received = transport.recv_from(&mut buf, deadline) => received?,
+            };
+            let actions = match received {
+                Some((len, addr)) => match DHCPMessage::try_from(&buf[..len]) {
+                    Ok(message) if self.accepts(&message) => {
+                        if self.state == DhcpState::Selecting
+                            && message.message_type() == Some(DHCPMessageType::DHCPOFFER)
+                        {
+                            self.emit(DhcpEvent::OfferReceived(Box::new(message.clone())));
+                        }
+                        self.handle_message(message, Instant::now())
+                    }
+                    Ok(message) => {
+                        log!(
+                            self,
+                            "Ignoring DHCP {:?} from {} for xid {:#010x}",
+                            message.message_type(),
+                            addr,
+                            message.xid
+                        );
+                        continue;
+                    }
+                    Err(e) => {
+                        log!(self, "Invalid DHCP message from {}: {}", addr, e);
+                        continue;
+                    }
+                },
+                None => self.handle_timeout(Instant::now()),
+            };
+            self.perform(transport, actions).await?;
+        }
+    }
+
+    // DHCPINFORM exchange for addr, returns the server's DHCPACK
+    pub async fn inform<T: DhcpTransport>(
+        &mut self,
+        transport: &T,
+        addr: Ipv4Addr,
+    ) -> Result<DHCPMessage, DhcpClientError> {
+        let mut actions = self.start_inform(addr, Instant::now());
+        let mut buf = [0u8; DHCP_MAX_MESSAGE_SIZE as usize];
+        loop {
+            let informed = actions.iter().find_map(|action| match action {
+                DhcpAction::Informed(ack) => Some(ack.clone()),
+                _ => None,
+            });
+            if let Some(ack) = informed {
+                return Ok(*ack);
+            }
+            self.perform(transport, actions).await?;
+            actions = match transport.recv_from(&mut buf, self.timeout()).await? {
+                Some((len, _)) => match DHCPMessage::try_from(&buf[..len]) {
+                    Ok(message) => self.handle_message(message, Instant::now()),
+                    Err(_) => Vec::new(),
+                },
+                None => self.handle_timeout(Instant::now()),
+            };
+        }
+    }
+
+    pub async fn release_lease<T: DhcpTransport>(
+        &mut self,
+        transport: &T,
+    ) -> Result<(), DhcpClientError> {
+        let actions = self.release(Instant::now());
+        self.perform(transport, actions).await
+    }
+
+    async fn perform<T: DhcpTransport>(
+        &mut self,
+        transport: &T,
+        actions: Vec<DhcpAction>,
+    ) -> Result<(), DhcpClientError> {
+        let mut actions = VecDeque::from(actions);
+        while let Some(action) = actions.pop_front() {
+            match action {
+                DhcpAction::Send(message, destination) => {
+                    log!(self, "DHCP {:?} -> {:?}", message.message_type(), destination);
+                    transport.send_to(&message.to_bytes(), destination).await?;
+                }
+                DhcpAction::Bound(lease) => {
+                    log!(self, "DHCP bound: {}", lease);
+                    self.rebooting = None;
+                    self.configure(&lease).await;
+                    self.save_lease(&lease);
+                    self.apply_dns(&lease).await;
+                    self.hook(DhcpHookReason::Bound, Some(&lease)).await;
+                    self.emit(DhcpEvent::Bound(lease));
+                }
+                DhcpAction::Renewed(lease) => {
+                    log!(self, "DHCP renewed: {}", lease);
+                    self.configure(&lease).await;
+                    self.save_lease(&lease);
+                    self.apply_dns(&lease).await;
+                    self.hook(DhcpHookReason::Renew, Some(&lease)).await;
+                    self.emit(DhcpEvent::Renewed(lease));
+                }
+                DhcpAction::Rebound(lease) => {
+                    log!(self, "DHCP rebound: {}", lease);
+                    self.configure(&lease).await;
+                    self.save_lease(&lease);
+                    self.apply_dns(&lease).await;
+                    self.hook(DhcpHookReason::Rebind, Some(&lease)).await;
+                    self.emit(DhcpEvent::Rebound(lease));
+                }
+                DhcpAction::Expired(lease) => {
+                    log!(self, "DHCP expired: {}", lease.address);
+                    self.remove_lease();
+                    self.unconfigure(&lease).await;
+                    self.revert_dns().await;
+                    self.hook(DhcpHookReason::Expire, Some(&lease)).await;
+                    self.emit(DhcpEvent::Expired(lease));
+                }
+                DhcpAction::Released(lease) => {
+                    log!(self, "DHCP released: {}", lease.address);
+                    self.remove_lease();
+                    self.unconfigure(&lease).await;
+                    self.revert_dns().await;
+                    self.hook(DhcpHookReason::Release, Some(&lease)).await;
+                    self.emit(DhcpEvent::Released(lease));
+                }
+                DhcpAction::Nak { message, lease } => {
+                    log!(
+                        self,
+                        "DHCP NAK: {}",
+                        message.as_deref().unwrap_or("no message from server")
+                    );
+                    // INIT-REBOOT holds no lease yet, only the file of the
+                    // previous run
+                    let lease =
+                        lease.or_else(|| self.rebooting.take().map(|record| record.to_lease()));
+                    if let Some(lease) = &lease {
+                        log!(self, "DHCP dropping lease {}", lease.address);
+                        self.remove_lease();
+                        self.unconfigure(lease).await;
+                        self.revert_dns().await;
+                    }
+                    self.hook(DhcpHookReason::Nak, lease.as_ref()).await;
+                    self.emit(DhcpEvent::Nak { message, lease });
+                }
+                DhcpAction::Probe(addr) => {
+                    let conflict = self.probe(addr).await;
+                    actions.extend(self.handle_probe(conflict, Instant::now()));
+                }
+                DhcpAction::Declined(lease) => {
+                    log!(self, "DHCP declined {}, address already in use", lease.address)
+                }
+                DhcpAction::OfferRejected(offer, reason) => log!(
+                    self,
+                    "DHCP rejected offer ({:?}) of {} from {:?}: lease {:?}s, mask {:?}, routers {:?}, dns {:?}",
+                    reason,
+                    offer.yiaddr,
+                    offer.server_identifier(),
+                    offer.lease_time(),
+                    offer.subnet_mask(),
+                    offer.routers(),
+                    offer.dns_servers()
+                ),
+                DhcpAction::Informed(ack) => {
+                    log!(self, "DHCP informed by {:?}", ack.server_identifier())
+                }
+                DhcpAction::TimedOut(state) => {
+                    return Err(DhcpClientError::Timeout {
+                        state,
+                        attempts: self.attempts + 1,
+                    })
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    async fn configure(&mut self, lease: &DhcpLease) {
+        let Some(interface) = self.interface_to_configure().map(str::to_string) else {
+            return;
+        };
+        let result = match super::netlink::DhcpNetlink::connect(&interface) {
+            Ok(mut netlink) => {
+                netlink.mtu = self.mtu;
+                let result = netlink.apply(lease).await;
+                self.mtu = netlink.mtu;
+                result
+            }
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            log!(self, "DHCP failed to configure {}: {}", interface, e);
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    async fn unconfigure(&mut self, lease: &DhcpLease) {
+        let Some(interface) = self.interface_to_configure().map(str::to_string) else {
+            return;
+        };
+        let result = match super::netlink::DhcpNetlink::connect(&interface) {
+            Ok(mut netlink) => {
+                netlink.mtu = self.mtu.take();
+                netlink.remove(lease).await
+            }
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            log!(self, "DHCP failed to unconfigure {}: {}", interface, e);
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    async fn configure(&mut self, _lease: &DhcpLease) {
+        if self.interface_to_configure().is_some() {
+            log!(
+                self,
+                "DHCP interface configuration is only supported on Linux"
+            );
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    async fn unconfigure(&mut self, _lease: &DhcpLease) {}
+
+    async fn apply_dns(&self, lease: &DhcpLease) {
+        let Some(resolver) = &self.config.resolver else {
+            return;
+        };
+        let interface = self.config.interface.as_deref().unwrap_or("default");
+        let settings = DhcpDnsSettings::from_lease(lease);
+        if let Err(e) = resolver.apply(interface, &settings).await {
+            log!(
+                self,
+                "DHCP failed to set DNS servers {:?}: {}",
+                settings.servers,
+                e
+            );
+        }
+    }
+
+    async fn revert_dns(&self) {
+        let Some(resolver) = &self.config.resolver else {
+            return;
+        };
+        let interface = self.config.interface.as_deref().unwrap_or("default");
+        if let Err(e) = resolver.revert(interface).await {
+            log!(self, "DHCP failed to revert DNS settings: {}", e);
+        }
+    }
+
+    // BOUND, RENEW and REBIND pass the new lease, the others the one that
+    // is given up
+    async fn hook(&mut self, reason: DhcpHookReason, lease: Option<&DhcpLease>) {
+        let (old, new) = match reason {
+            DhcpHookReason::Bound | DhcpHookReason::Renew | DhcpHookReason::Rebind => {
+                (std::mem::replace(&mut self.reported, lease.cloned()), lease)
+            }
+            _ => (self.reported.take().or(lease.cloned()), None),
+        };
+        let Some(path) = &self.config.hook else {
+            return;
+        };
+        let interface = self.config.interface.as_deref().unwrap_or_default();
+        match run_hook(path, reason, interface, old.as_ref(), new).await {
+            Ok(status) if !status.success() => log!(
+                self,
+                "DHCP hook {} {} exited with {}",
+                path.display(),
+                reason.as_str(),
+                status
+            ),
+            Ok(_) => {}
+            Err(e) => log!(self, "DHCP failed to run hook {}: {}", path.display(), e),
+        }
+    }
+
+    fn interface_to_configure(&self) -> Option<&str> {
+        if !self.config.configure_interface {
+            return None;
+        }
+        let interface = self.config.interface.as_deref();
+        if interface.is_none() {
+            log!(
+                self,
+                "DHCP no interface configured, leaving the lease unapplied"
+            );
+        }
+        interface
+    }
+
+    // the lease left by a previous run, if it is still ours to ask for in
+    // INIT-REBOOT
+    fn load_lease(&mut self) -> Option<DhcpLeaseRecord> {
+        let path = self.config.lease_file.as_ref()?;
+        let record = match DhcpLeaseRecord::load(path) {
+            Ok(record) => record,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return None,
+            Err(e) => {
+                log!(self, "DHCP failed to load lease {}: {}", path.display(), e);
+                return None;
+            }
+        };
+        if record.mac != self.config.mac {
+            log!(
+                self,
+                "DHCP ignoring lease {} of another hardware address {}",
+                path.display(),
+                record.mac
+            );
+            return None;
+        }
+        if record.is_expired() {
+            log!(self, "DHCP lease of {} has expired", record.address);
+            self.remove_lease();
+            return None;
+        }
+        log!(
+            self,
+            "DHCP rebooting with previous lease of {}",
+            record.address
+        );
+        self.mtu = record.mtu;
+        Some(record)
+    }
+
+    fn save_lease(&self, lease: &DhcpLease) {
+        let Some(path) = &self.config.lease_file else {
+            return;
+        };
+        let interface = self.config.interface.as_deref().unwrap_or_default();
+        let mut record = DhcpLeaseRecord::new(interface, self.config.mac, lease);
+        record.mtu = self.mtu;
+        if let Err(e) = record.save(path) {
+            log!(
+                self,
+                "DHCP failed to save lease to {}: {}",
+                path.display(),
+                e
+            );
+        }
+    }
+
+    fn remove_lease(&self) {
+        let Some(path) = &self.config.lease_file else {
+            return;
+        };
+        if let Err(e) = DhcpLeaseRecord::remove(path) {
+            log!(
+                self,
+                "DHCP failed to remove lease {}: {}",
+                path.display(),
+                e
+            );
+        }
+    }
+
+    async fn probe(&self, addr: Ipv4Addr) -> bool {
+        let Some(name) = self.config.interface.clone() else {
+            log!(
+                self,
+                "DHCP no interface configured, skipping ARP probe of {}",
+                addr
+            );
+            return false;
+        };
+        let mac = self.config.mac;
+        let result = tokio::task::spawn_blocking(move || {
+            let interface = find_interface(&name)?;
+            arp::probe(&interface, mac, addr)
+        })
+        .await
+        .unwrap_or_else(|e| Err(io::Error::other(e)));
+        match result {
+            Ok(Some(owner)) => {
+                log!(self, "DHCP {} is already used by {}", addr, owner);
+                true
+            }
+            Ok(None) => false,
+            Err(e) => {
+                log!(self, "DHCP ARP probe of {} failed: {}", addr, e);
+                false
+            }
+        }
+    }
+}
+
+// half of the time left until the next state change, at least
+// DHCP_MIN_RETRANSMIT_SECS and never past it
+fn retransmit_at(now: Instant, next: Option<Instant>) -> Option<Instant> {
+    let next = next?;
+    let wait = (next.saturating_duration_since(now) / 2)
+        .max(Duration::from_secs(DHCP_MIN_RETRANSMIT_SECS));
+    Some((now + wait).min(next))
+}
+
+fn backoff(attempt: u32) -> Duration {
+    let secs = DHCP_INITIAL_BACKOFF_SECS
+        .saturating_mul(1 << attempt.min(16))
+        .min(DHCP_MAX_BACKOFF_SECS);
+    let jitter = rand::thread_rng().gen_range(-1000..=1000);
+    Duration::from_millis((secs * 1000).saturating_add_signed(jitter))
+}
@@ -0,0 +1,255 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use pnet::util::MacAddr;
+use serde::{Deserialize, Serialize};
+
+use super::lease::save_json;
+use super::{DHCPHType, DhcpOptionValue};
+
+pub const DHCP_DUID_FILE: &str = "/var/lib/inode/duid.json";
+// RFC 4361 client identifiers are type 255 followed by IAID and DUID
+pub const DHCP_DUID_CLIENT_IDENTIFIER_TYPE: u8 = 255;
+// DUID-LLT counts seconds from midnight of 2000-01-01 UTC
+const DUID_TIME_EPOCH: u64 = 946_684_800;
+const PRODUCT_UUID: &str = "/sys/class/dmi/id/product_uuid";
+
+// RFC 8415 DUID, kept on disk so the client stays the same across NIC
+// replacements and restarts
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(
+    tag = "type",
+    rename_all = "kebab-case",
+    rename_all_fields = "kebab-case"
+)]
+pub enum DhcpDuid {
+    Llt {
+        hardware_type: u16,
+        time: u32,
+        address: MacAddr,
+    },
+    En {
+        enterprise_number: u32,
+        identifier: Vec<u8>,
+    },
+    Ll {
+        hardware_type: u16,
+        address: MacAddr,
+    },
+    Uuid {
+        uuid: [u8; 16],
+    },
+}
+
+impl DhcpDuid {
+    pub fn llt(mac: MacAddr) -> Self {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        DhcpDuid::Llt {
+            hardware_type: DHCPHType::ETHERNET as u16,
+            time: now.saturating_sub(DUID_TIME_EPOCH) as u32,
+            address: mac,
+        }
+    }
+
+    // identifier is random, the enterprise number tells whose scheme it is
+    pub fn en(enterprise_number: u32) -> Self {
+        DhcpDuid::En {
+            enterprise_number,
+            identifier: rand::random::<[u8; 8]>().to_vec(),
+        }
+    }
+
+    pub fn ll(mac: MacAddr) -> Self {
+        DhcpDuid::Ll {
+            hardware_type: DHCPHType::ETHERNET as u16,
+            address: mac,
+        }
+    }
+
+    // the SMBIOS system UUID when readable, a random version 4 UUID otherwise
+    pub fn uuid() -> Self {
+        let uuid = fs::read_to_string(PRODUCT_UUID)
+            .ok()
+            .and_then(|uuid| parse_uuid(&uuid))
+            .unwrap_or_else(|| {
+                let mut uuid: [u8; 16] = rand::random();
+                uuid[6] = (uuid[6] & 0x0F) | 0x40;
+                uuid[8] = (uuid[8] & 0x3F) | 0x80;
+                uuid
+            });
+        DhcpDuid::Uuid { uuid }
+    }
+
+    pub fn generate(tp: &DhcpDuidType, mac: MacAddr) -> Self {
+        match tp {
+            DhcpDuidType::Llt => DhcpDuid::llt(mac),
+            DhcpDuidType::En { enterprise_number } => DhcpDuid::en(*enterprise_number),
+            DhcpDuidType::Ll => DhcpDuid::ll(mac),
+            DhcpDuidType::Uuid => DhcpDuid::uuid(),
+        }
+    }
+
+    pub fn is(&self, tp: &DhcpDuidType) -> bool {
+        match (self, tp) {
+            (DhcpDuid::Llt { .. }, DhcpDuidType::Llt)
+            | (DhcpDuid::Ll { .. }, DhcpDuidType::Ll)
+            | (DhcpDuid::Uuid { .. }, DhcpDuidType::Uuid) => true,
+            (
+                DhcpDuid::En {
+                    enterprise_number, ..
+                },
+                DhcpDuidType::En {
+                    enterprise_number: wanted,
+                },
+            ) => enterprise_number == wanted,
+            _ => false,
+        }
+    }
+
+    // wire format, a 2 byte DUID type followed by the type's fields
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        match self {
+            DhcpDuid::Llt {
+                hardware_type,
+                time,
+                address,
+            } => {
+                bytes.extend(1u16.to_be_bytes());
+                bytes.extend(hardware_type.to_be_bytes());
+                bytes.extend(time.to_be_bytes());
+                bytes.extend(address.octets());
+            }
+            DhcpDuid::En {
+                enterprise_number,
+                identifier,
+            } => {
+                bytes.extend(2u16.to_be_bytes());
+                bytes.extend(enterprise_number.to_be_bytes());
+                bytes.extend(identifier);
+            }
+            DhcpDuid::Ll {
+                hardware_type,
+                address,
+            } => {
+                bytes.extend(3u16.to_be_bytes());
+                bytes.extend(hardware_type.to_be_bytes());
+                bytes.extend(address.octets());
+            }
+            DhcpDuid::Uuid { uuid } => {
+                bytes.extend(4u16.to_be_bytes());
+                bytes.extend(uuid);
+            }
+        }
+        bytes
+    }
+
+    // the DUID stored at path, or a new one of type tp stored there when
+    // there is none yet or the config asks for another type
+    pub fn load_or_generate<P: AsRef<Path>>(
+        path: P,
+        tp: &DhcpDuidType,
+        mac: MacAddr,
+    ) -> io::Result<Self> {
+        let path = path.as_ref();
+        match DhcpDuid::load(path) {
+            Ok(duid) if duid.is(tp) => return Ok(duid),
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+        let duid = DhcpDuid::generate(tp, mac);
+        duid.save(path)?;
+        Ok(duid)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = fs::File::open(path)?;
+        Ok(serde_json::from_reader(io::BufReader::new(file))?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        save_json(path.as_ref(), self)
+    }
+}
+
+fn parse_uuid(uuid: &str) -> Option<[u8; 16]> {
+    let digits: Vec<u8> = uuid
+        .trim()
+        .chars()
+        .filter(|&c| c != '-')
+        .map(|c| c.to_digit(16).map(|digit| digit as u8))
+        .collect::<Option<_>>()?;
+    let bytes: Vec<u8> = digits
+        .chunks(2)
+        .map(|pair| pair[0] << 4 | pair[1])
+        .collect();
+    let uuid: [u8; 16] = bytes.try_into().ok()?;
+    // firmware without a UUID reports all zeros or all ones
+    if uuid.iter().all(|&b| b == 0) || uuid.iter().all(|&b| b == 0xFF) {
+        return None;
+    }
+    Some(uuid)
+}
+
+// The duid section of the config file, e.g.
+// {"type": "en", "enterprise-number": 32473, "iaid": {"eth0": 1}}
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct DhcpDuidConfig {
+    #[serde(flatten)]
+    pub tp: DhcpDuidType,
+    #[serde(default = "default_duid_file")]
+    pub file: PathBuf,
+    // IAIDs of interfaces, others get one derived from their name
+    #[serde(default)]
+    pub iaid: BTreeMap<String, u32>,
+}
+
+fn default_duid_file() -> PathBuf {
+    PathBuf::from(DHCP_DUID_FILE)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(
+    tag = "type",
+    rename_all = "kebab-case",
+    rename_all_fields = "kebab-case"
+)]
+pub enum DhcpDuidType {
+    Llt,
+    En { enterprise_number: u32 },
+    Ll,
+    Uuid,
+}
+
+impl DhcpDuidConfig {
+    pub fn iaid(&self, interface: &str) -> u32 {
+        self.iaid
+            .get(interface)
+            .copied()
+            .unwrap_or_else(|| iaid(interface))
+    }
+}
+
+// FNV-1a of the interface name, so the IAID survives NIC replacements
+// like the DUID does
+pub fn iaid(interface: &str) -> u32 {
+    interface.bytes().fold(0x811C_9DC5, |hash, byte| {
+        (hash ^ byte as u32).wrapping_mul(0x0100_0193)
+    })
+}
+
+pub fn client_identifier(iaid: u32, duid: &DhcpDuid) -> DhcpOptionValue {
+    let mut id = iaid.to_be_bytes().to_vec();
+    id.extend(duid.to_bytes());
+    DhcpOptionValue::ClientIdentifier {
+        tp: DHCP_DUID_CLIENT_IDENTIFIER_TYPE,
+        id,
+    }
+}
@@ -0,0 +1,117 @@
+use std::io;
+use std::net::Ipv4Addr;
+use std::path::Path;
+use std::process::ExitStatus;
+
+use tokio::process::Command;
+
+use super::client::DhcpLease;
+use super::DHCPOPTION;
+
+// the $reason a dhclient-script is called with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DhcpHookReason {
+    Bound,
+    Renew,
+    Rebind,
+    Expire,
+    Release,
+    Nak,
+}
+
+impl DhcpHookReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DhcpHookReason::Bound => "BOUND",
+            DhcpHookReason::Renew => "RENEW",
+            DhcpHookReason::Rebind => "REBIND",
+            DhcpHookReason::Expire => "EXPIRE",
+            DhcpHookReason::Release => "RELEASE",
+            DhcpHookReason::Nak => "NAK",
+        }
+    }
+}
+
+fn join<T: ToString>(values: &[T]) -> String {
+    values
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// dhclient-script variables of a lease, named <prefix>_ip_address and so on
+pub fn lease_env(prefix: &str, lease: &DhcpLease) -> Vec<(String, String)> {
+    let parameters = lease.parameters();
+    let mut env = vec![
+        ("ip_address", lease.address.to_string()),
+        (
+            "dhcp_server_identifier",
+            lease.server_identifier.to_string(),
+        ),
+        ("dhcp_lease_time", lease.lease_time.to_string()),
+        ("dhcp_renewal_time", lease.renewal_time.to_string()),
+        ("dhcp_rebinding_time", lease.rebinding_time.to_string()),
+    ];
+    if let Some(mask) = parameters.subnet_mask() {
+        let network = u32::from(lease.address) & u32::from(mask);
+        env.push(("subnet_mask", mask.to_string()));
+        env.push(("network_number", Ipv4Addr::from(network).to_string()));
+    }
+    if let Some(broadcast) = parameters.broadcast_address() {
+        env.push(("broadcast_address", broadcast.to_string()));
+    }
+    let lists = [
+        ("routers", parameters.routers()),
+        ("domain_name_servers", parameters.dns_servers()),
+        ("ntp_servers", parameters.ntp_servers()),
+    ];
+    for (name, addresses) in lists {
+        if !addresses.is_empty() {
+            env.push((name, join(&addresses)));
+        }
+    }
+    if let Some(domain) = parameters.domain_name() {
+        env.push(("domain_name", domain));
+    }
+    let search = parameters.domain_search();
+    if !search.is_empty() {
+        env.push(("domain_search", join(&search)));
+    }
+    if let Some(host_name) = parameters.host_name() {
+        env.push(("host_name", host_name));
+    }
+    if let Some(mtu) = parameters.interface_mtu() {
+        env.push(("interface_mtu", mtu.to_string()));
+    }
+    // dhclient passes the option bytes in decimal and leaves parsing to
+    // the script
+    if let Some(routes) = parameters.option(DHCPOPTION::ClasslessStaticRoute) {
+        env.push(("rfc3442_classless_static_routes", join(&routes.va)));
+    }
+    env.into_iter()
+        .map(|(name, value)| (format!("{}_{}", prefix, name), value))
+        .collect()
+}
+
+// runs the hook like dhclient runs dhclient-script, with old_* from the
+// lease given up and new_* from the lease taken
+pub async fn run_hook(
+    path: &Path,
+    reason: DhcpHookReason,
+    interface: &str,
+    old: Option<&DhcpLease>,
+    new: Option<&DhcpLease>,
+) -> io::Result<ExitStatus> {
+    let mut command = Command::new(path);
+    command
+        .env("reason", reason.as_str())
+        .env("interface", interface);
+    if let Some(old) = old {
+        command.envs(lease_env("old", old));
+    }
+    if let Some(new) = new {
+        command.envs(lease_env("new", new));
+    }
+    command.status().await
+}
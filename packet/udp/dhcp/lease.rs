@@ -0,0 +1,121 @@
+use std::fs;
+use std::io;
+use std::net::Ipv4Addr;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use pnet::util::MacAddr;
+use serde::{Deserialize, Serialize};
+
+use super::client::DhcpLease;
+use super::DHCPOPTIONS;
+
+pub const DHCP_LEASE_DIR: &str = "/var/lib/inode";
+
+pub fn lease_path<P: AsRef<Path>>(dir: P, interface: &str) -> PathBuf {
+    dir.as_ref().join(format!("dhcp-{}.json", interface))
+}
+
+// writes a sibling and renames it, so a crash never leaves half a file
+pub fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut tmp = path.as_os_str().to_os_string();
+    tmp.push(".tmp");
+    fs::write(&tmp, contents)?;
+    fs::rename(tmp, path)
+}
+
+pub fn save_json<T: Serialize>(path: &Path, value: &T) -> io::Result<()> {
+    write_atomic(path, &serde_json::to_vec_pretty(value)?)
+}
+
+// DhcpLease as stored on disk, with wall clock instead of monotonic times
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DhcpLeaseRecord {
+    pub interface: String,
+    pub mac: MacAddr,
+    pub address: Ipv4Addr,
+    pub server_identifier: Ipv4Addr,
+    pub lease_time: u32,
+    pub renewal_time: u32,
+    pub rebinding_time: u32,
+    // seconds since the unix epoch
+    pub acquired: u64,
+    pub options: Vec<DHCPOPTIONS>,
+    // MTU of the interface before the lease changed it
+    #[serde(default)]
+    pub mtu: Option<u32>,
+}
+
+impl DhcpLeaseRecord {
+    pub fn new(interface: &str, mac: MacAddr, lease: &DhcpLease) -> Self {
+        let age = Instant::now().saturating_duration_since(lease.acquired);
+        let acquired = SystemTime::now()
+            .checked_sub(age)
+            .unwrap_or(UNIX_EPOCH)
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        DhcpLeaseRecord {
+            interface: interface.to_string(),
+            mac,
+            address: lease.address,
+            server_identifier: lease.server_identifier,
+            lease_time: lease.lease_time,
+            renewal_time: lease.renewal_time,
+            rebinding_time: lease.rebinding_time,
+            acquired: acquired.as_secs(),
+            options: lease.options.clone(),
+            mtu: None,
+        }
+    }
+
+    pub fn age(&self) -> Duration {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH + Duration::from_secs(self.acquired))
+            .unwrap_or_default()
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.lease_time != u32::MAX && self.age() >= Duration::from_secs(self.lease_time as u64)
+    }
+
+    // the lease starts now with its times shortened by its age, so the
+    // deadlines stay where they were
+    pub fn to_lease(&self) -> DhcpLease {
+        let age = self.age().as_secs().min(u32::MAX as u64) as u32;
+        let left = |secs: u32| {
+            if self.lease_time == u32::MAX {
+                secs
+            } else {
+                secs.saturating_sub(age)
+            }
+        };
+        DhcpLease {
+            address: self.address,
+            server_identifier: self.server_identifier,
+            lease_time: left(self.lease_time),
+            renewal_time: left(self.renewal_time),
+            rebinding_time: left(self.rebinding_time),
+            acquired: Instant::now(),
+            options: self.options.clone(),
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = fs::File::open(path)?;
+        Ok(serde_json::from_reader(io::BufReader::new(file))?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        save_json(path.as_ref(), self)
+    }
+
+    pub fn remove<P: AsRef<Path>>(path: P) -> io::Result<()> {
+        match fs::remove_file(path) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use std::fs;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr};
+
+use rtnetlink::Handle;
+
+use super::client::{find_interface, DhcpLease};
+
+// errno values meaning there was nothing to remove
+const ENOENT: i32 = 2;
+const ESRCH: i32 = 3;
+const EADDRNOTAVAIL: i32 = 99;
+
+// Configures an interface with a DhcpLease over rtnetlink: the address with
+// the prefix of its SubnetMask, a default route through the first
+// RouterOption and the InterfaceMTUOption.
+pub struct DhcpNetlink {
+    handle: Handle,
+    index: u32,
+    interface: String,
+    // MTU of the interface before apply changed it, put back by remove
+    pub mtu: Option<u32>,
+}
+
+impl DhcpNetlink {
+    // needs a tokio runtime, the netlink connection runs as a task
+    pub fn connect(interface: &str) -> io::Result<Self> {
+        let index = find_interface(interface)?.index;
+        let (connection, handle, _) = rtnetlink::new_connection()?;
+        tokio::spawn(connection);
+        Ok(DhcpNetlink {
+            handle,
+            index,
+            interface: interface.to_string(),
+            mtu: None,
+        })
+    }
+
+    // replaces what is already there, so applying a renewed lease again is
+    // fine; mtu keeps the MTU found before the first change
+    pub async fn apply(&mut self, lease: &DhcpLease) -> io::Result<()> {
+        self.handle
+            .address()
+            .add(self.index, IpAddr::V4(lease.address), prefix(lease))
+            .replace()
+            .execute()
+            .await
+            .map_err(io::Error::other)?;
+        let parameters = lease.parameters();
+        if let Some(mtu) = parameters.interface_mtu() {
+            if self.mtu.is_none() {
+                self.mtu = Some(current_mtu(&self.interface)?);
+            }
+            self.handle
+                .link()
+                .set(self.index)
+                .mtu(mtu as u32)
+                .execute()
+                .await
+                .map_err(io::Error::other)?;
+        }
+        if let Some(router) = parameters.routers().first() {
+            self.handle
+                .route()
+                .add()
+                .v4()
+                .destination_prefix(Ipv4Addr::UNSPECIFIED, 0)
+                .gateway(*router)
+                .output_interface(self.index)
+                .replace()
+                .execute()
+                .await
+                .map_err(io::Error::other)?;
+        }
+        Ok(())
+    }
+
+    // takes off the route and the address and puts back the MTU; things
+    // already gone are not an error
+    pub async fn remove(&self, lease: &DhcpLease) -> io::Result<()> {
+        if let Some(router) = lease.parameters().routers().first() {
+            let mut route = self
+                .handle
+                .route()
+                .add()
+                .v4()
+                .destination_prefix(Ipv4Addr::UNSPECIFIED, 0)
+                .gateway(*router)
+                .output_interface(self.index);
+            let message = route.message_mut().clone();
+            missing_is_ok(self.handle.route().del(message).execute().await)?;
+        }
+        let mut address =
+            self.handle
+                .address()
+                .add(self.index, IpAddr::V4(lease.address), prefix(lease));
+        let message = address.message_mut().clone();
+        missing_is_ok(self.handle.address().del(message).execute().await)?;
+        if let Some(mtu) = self.mtu {
+            self.handle
+                .link()
+                .set(self.index)
+                .mtu(mtu)
+                .execute()
+                .await
+                .map_err(io::Error::other)?;
+        }
+        Ok(())
+    }
+}
+
+fn current_mtu(interface: &str) -> io::Result<u32> {
+    fs::read_to_string(format!("/sys/class/net/{}/mtu", interface))?
+        .trim()
+        .parse()
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "unreadable MTU"))
+}
+
+// prefix length of the SubnetMask, or of the address class without one
+fn prefix(lease: &DhcpLease) -> u8 {
+    match lease.parameters().subnet_mask() {
+        Some(mask) => u32::from(mask).leading_ones() as u8,
+        None => match lease.address.octets()[0] {
+            0..=127 => 8,
+            128..=191 => 16,
+            _ => 24,
+        },
+    }
+}
+
+fn missing_is_ok(result: Result<(), rtnetlink::Error>) -> io::Result<()> {
+    match result {
+        Err(rtnetlink::Error::NetlinkError(e))
+            if matches!(
+                e.code.map(|code| -code.get()),
+                Some(ENOENT | ESRCH | EADDRNOTAVAIL)
+            ) =>
+        {
+            Ok(())
+        }
+        result => result.map_err(io::Error::other),
+    }
+}
@@ -0,0 +1,252 @@
+use std::collections::HashMap;
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use pnet::datalink::{self, Channel, DataLinkSender, NetworkInterface};
+use pnet::packet::ethernet::{EtherTypes, EthernetPacket, MutableEthernetPacket};
+use pnet::packet::ip::IpNextHeaderProtocols;
+use pnet::packet::ipv4::{self, Ipv4Flags, Ipv4Packet, MutableIpv4Packet};
+use pnet::packet::udp::{self, MutableUdpPacket, UdpPacket};
+use pnet::util::MacAddr;
+use tokio::sync::mpsc;
+
+use super::client::{find_interface, interface_mac, DHCP_CLIENT_PORT, DHCP_SERVER_PORT};
+
+const ETHERNET_HEADER_LEN: usize = 14;
+const IPV4_HEADER_LEN: usize = 20;
+const UDP_HEADER_LEN: usize = 8;
+const IPV4_TTL: u8 = 64;
+// replies waiting to be received before the reader drops frames
+const RAW_QUEUE_LEN: usize = 64;
+
+// Ethernet/IPv4/UDP frame carrying payload, checksums included
+pub fn udp_frame(
+    source: (MacAddr, SocketAddrV4),
+    destination: (MacAddr, SocketAddrV4),
+    payload: &[u8],
+) -> Vec<u8> {
+    let udp_len = UDP_HEADER_LEN + payload.len();
+    let ip_len = IPV4_HEADER_LEN + udp_len;
+    let mut frame = vec![0u8; ETHERNET_HEADER_LEN + ip_len];
+    {
+        let mut ethernet = MutableEthernetPacket::new(&mut frame).unwrap();
+        ethernet.set_destination(destination.0);
+        ethernet.set_source(source.0);
+        ethernet.set_ethertype(EtherTypes::Ipv4);
+    }
+    {
+        let mut udp =
+            MutableUdpPacket::new(&mut frame[ETHERNET_HEADER_LEN + IPV4_HEADER_LEN..]).unwrap();
+        udp.set_source(source.1.port());
+        udp.set_destination(destination.1.port());
+        udp.set_length(udp_len as u16);
+        udp.set_payload(payload);
+        let checksum = udp::ipv4_checksum(&udp.to_immutable(), source.1.ip(), destination.1.ip());
+        udp.set_checksum(checksum);
+    }
+    let mut ip = MutableIpv4Packet::new(&mut frame[ETHERNET_HEADER_LEN..]).unwrap();
+    ip.set_version(4);
+    ip.set_header_length((IPV4_HEADER_LEN / 4) as u8);
+    ip.set_total_length(ip_len as u16);
+    ip.set_flags(Ipv4Flags::DontFragment);
+    ip.set_ttl(IPV4_TTL);
+    ip.set_next_level_protocol(IpNextHeaderProtocols::Udp);
+    ip.set_source(*source.1.ip());
+    ip.set_destination(*destination.1.ip());
+    let checksum = ipv4::checksum(&ip.to_immutable());
+    ip.set_checksum(checksum);
+    frame
+}
+
+// What a BPF program on a DHCP client socket would accept: unfragmented
+// IPv4/UDP to the client port, sent to our MAC or to broadcast.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DhcpFrameFilter {
+    pub mac: MacAddr,
+    pub port: u16,
+}
+
+// the accepted part of a frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DhcpFrame<'a> {
+    pub source_mac: MacAddr,
+    pub source: SocketAddrV4,
+    pub payload: &'a [u8],
+}
+
+impl DhcpFrameFilter {
+    pub fn new(mac: MacAddr) -> Self {
+        DhcpFrameFilter {
+            mac,
+            port: DHCP_CLIENT_PORT,
+        }
+    }
+
+    pub fn matches<'a>(&self, frame: &'a [u8]) -> Option<DhcpFrame<'a>> {
+        let ethernet = EthernetPacket::new(frame)?;
+        let destination = ethernet.get_destination();
+        if ethernet.get_ethertype() != EtherTypes::Ipv4
+            || (destination != self.mac && !destination.is_broadcast())
+        {
+            return None;
+        }
+        let ip = Ipv4Packet::new(&frame[ETHERNET_HEADER_LEN..])?;
+        let header_len = ip.get_header_length() as usize * 4;
+        let total_len = ip.get_total_length() as usize;
+        if ip.get_version() != 4
+            || ip.get_next_level_protocol() != IpNextHeaderProtocols::Udp
+            || header_len < IPV4_HEADER_LEN
+            || total_len < header_len + UDP_HEADER_LEN
+            || total_len > frame.len() - ETHERNET_HEADER_LEN
+            || ip.get_flags() & Ipv4Flags::MoreFragments != 0
+            || ip.get_fragment_offset() != 0
+        {
+            return None;
+        }
+        let start = ETHERNET_HEADER_LEN + header_len;
+        let udp = UdpPacket::new(&frame[start..ETHERNET_HEADER_LEN + total_len])?;
+        let udp_len = udp.get_length() as usize;
+        if udp.get_destination() != self.port
+            || udp_len < UDP_HEADER_LEN
+            || udp_len > total_len - header_len
+        {
+            return None;
+        }
+        Some(DhcpFrame {
+            source_mac: ethernet.get_source(),
+            source: SocketAddrV4::new(ip.get_source(), udp.get_source()),
+            payload: &frame[start + UDP_HEADER_LEN..start + udp_len],
+        })
+    }
+}
+
+// payload, source and source MAC of an accepted frame, owned to cross threads
+type RawReply = io::Result<(Vec<u8>, SocketAddrV4, MacAddr)>;
+
+// DHCP over hand made frames on one interface, usable before the interface
+// has an address and independent of the host's routing
+pub struct DhcpRawSocket {
+    interface: NetworkInterface,
+    mac: MacAddr,
+    tx: Mutex<Box<dyn DataLinkSender>>,
+    rx: tokio::sync::Mutex<mpsc::Receiver<RawReply>>,
+    // hardware addresses of the servers we heard from, for unicast
+    servers: Mutex<HashMap<Ipv4Addr, MacAddr>>,
+}
+
+impl DhcpRawSocket {
+    pub fn bind(name: &str) -> io::Result<Self> {
+        let interface = find_interface(name)?;
+        let mac = interface_mac(&interface)?;
+        Self::bind_interface(interface, mac)
+    }
+
+    pub fn bind_interface(interface: NetworkInterface, mac: MacAddr) -> io::Result<Self> {
+        let config = datalink::Config {
+            read_timeout: Some(Duration::from_millis(100)),
+            ..Default::default()
+        };
+        let (tx, mut rx) = match datalink::channel(&interface, config)? {
+            Channel::Ethernet(tx, rx) => (tx, rx),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "unsupported datalink channel",
+                ))
+            }
+        };
+        let filter = DhcpFrameFilter::new(mac);
+        let (queue, replies) = mpsc::channel(RAW_QUEUE_LEN);
+        // the datalink receiver blocks, so it gets a thread of its own that
+        // ends once the socket is dropped
+        std::thread::spawn(move || loop {
+            if queue.is_closed() {
+                return;
+            }
+            let reply = match rx.next() {
+                Ok(frame) => match filter.matches(frame) {
+                    Some(frame) => (frame.payload.to_vec(), frame.source, frame.source_mac),
+                    None => continue,
+                },
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+                    ) =>
+                {
+                    continue
+                }
+                Err(e) => {
+                    let _ = queue.blocking_send(Err(e));
+                    return;
+                }
+            };
+            if queue.blocking_send(Ok(reply)).is_err() {
+                return;
+            }
+        });
+        Ok(DhcpRawSocket {
+            interface,
+            mac,
+            tx: Mutex::new(tx),
+            rx: tokio::sync::Mutex::new(replies),
+            servers: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn network_interface(&self) -> &NetworkInterface {
+        &self.interface
+    }
+
+    pub fn mac(&self) -> MacAddr {
+        self.mac
+    }
+
+    // sends a DHCP message from the client port, the source address is the
+    // message's ciaddr so frames are right before and after we have a lease
+    pub async fn send_to(&self, payload: &[u8], destination: SocketAddrV4) -> io::Result<usize> {
+        let ciaddr = payload
+            .get(12..16)
+            .map(|ciaddr| Ipv4Addr::new(ciaddr[0], ciaddr[1], ciaddr[2], ciaddr[3]))
+            .unwrap_or(Ipv4Addr::UNSPECIFIED);
+        let destination_mac = if destination.ip().is_broadcast() {
+            MacAddr::broadcast()
+        } else {
+            // without ARP an unknown server is reached by broadcast
+            let servers = self.servers.lock().unwrap();
+            servers
+                .get(destination.ip())
+                .copied()
+                .unwrap_or(MacAddr::broadcast())
+        };
+        let frame = udp_frame(
+            (self.mac, SocketAddrV4::new(ciaddr, DHCP_CLIENT_PORT)),
+            (destination_mac, destination),
+            payload,
+        );
+        let mut tx = self.tx.lock().unwrap();
+        match tx.send_to(&frame, None) {
+            Some(result) => result.map(|_| payload.len()),
+            None => Err(io::Error::other("datalink sender closed")),
+        }
+    }
+
+    pub async fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        let mut rx = self.rx.lock().await;
+        let (payload, source, source_mac) = rx
+            .recv()
+            .await
+            .ok_or_else(|| io::Error::other("datalink receiver closed"))??;
+        if source.port() == DHCP_SERVER_PORT {
+            self.servers
+                .lock()
+                .unwrap()
+                .insert(*source.ip(), source_mac);
+        }
+        let len = payload.len().min(buf.len());
+        buf[..len].copy_from_slice(&payload[..len]);
+        Ok((len, SocketAddr::V4(source)))
+    }
+}
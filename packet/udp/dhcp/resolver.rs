@@ -0,0 +1,277 @@
+use std::fs;
+use std::future::Future;
+use std::io;
+use std::net::Ipv4Addr;
+use std::path::PathBuf;
+use std::process::Stdio;
+
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+use super::client::DhcpLease;
+use super::lease::write_atomic;
+
+pub const RESOLV_CONF: &str = "/etc/resolv.conf";
+// first line of every resolv.conf we write, so we only restore over our own
+const RESOLV_CONF_HEADER: &str = "# generated by inode";
+
+// what a lease says about name resolution
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DhcpDnsSettings {
+    pub servers: Vec<Ipv4Addr>,
+    // DomainSearch, or DomainName when the server sends no search list
+    pub search: Vec<String>,
+}
+
+impl DhcpDnsSettings {
+    pub fn from_lease(lease: &DhcpLease) -> Self {
+        let parameters = lease.parameters();
+        let mut search = parameters.domain_search();
+        if search.is_empty() {
+            search.extend(parameters.domain_name());
+        }
+        DhcpDnsSettings {
+            servers: parameters.dns_servers(),
+            search,
+        }
+    }
+
+    // resolv.conf(5) text
+    pub fn resolv_conf(&self, interface: &str) -> String {
+        let mut conf = format!("{} for {}\n", RESOLV_CONF_HEADER, interface);
+        if !self.search.is_empty() {
+            conf.push_str(&format!("search {}\n", self.search.join(" ")));
+        }
+        for server in &self.servers {
+            conf.push_str(&format!("nameserver {}\n", server));
+        }
+        conf
+    }
+}
+
+// Where the DNS settings of a lease go. revert undoes apply for the
+// interface.
+pub trait DhcpResolver: Send + Sync {
+    fn apply(
+        &self,
+        interface: &str,
+        settings: &DhcpDnsSettings,
+    ) -> impl Future<Output = io::Result<()>> + Send;
+
+    fn revert(&self, interface: &str) -> impl Future<Output = io::Result<()>> + Send;
+}
+
+// The resolver section of the config file, e.g.
+// {"backend": "resolv-conf", "path": "/etc/resolv.conf"}
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "backend", rename_all = "kebab-case")]
+pub enum DhcpResolverConfig {
+    ResolvConf(DhcpResolvConfFile),
+    Resolvconf(DhcpResolvconf),
+    SystemdResolved(DhcpSystemdResolved),
+}
+
+impl DhcpResolver for DhcpResolverConfig {
+    async fn apply(&self, interface: &str, settings: &DhcpDnsSettings) -> io::Result<()> {
+        match self {
+            DhcpResolverConfig::ResolvConf(backend) => backend.apply(interface, settings).await,
+            DhcpResolverConfig::Resolvconf(backend) => backend.apply(interface, settings).await,
+            DhcpResolverConfig::SystemdResolved(backend) => {
+                backend.apply(interface, settings).await
+            }
+        }
+    }
+
+    async fn revert(&self, interface: &str) -> io::Result<()> {
+        match self {
+            DhcpResolverConfig::ResolvConf(backend) => backend.revert(interface).await,
+            DhcpResolverConfig::Resolvconf(backend) => backend.revert(interface).await,
+            DhcpResolverConfig::SystemdResolved(backend) => backend.revert(interface).await,
+        }
+    }
+}
+
+// Writes resolv.conf directly. The file found there first, which may be a
+// symlink, is moved aside and moved back on revert. There is one file for
+// the whole host, so this backend serves a single interface only.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DhcpResolvConfFile {
+    pub path: PathBuf,
+    // defaults to path with a .inode suffix
+    pub backup: Option<PathBuf>,
+}
+
+impl Default for DhcpResolvConfFile {
+    fn default() -> Self {
+        DhcpResolvConfFile {
+            path: PathBuf::from(RESOLV_CONF),
+            backup: None,
+        }
+    }
+}
+
+impl DhcpResolvConfFile {
+    pub fn backup_path(&self) -> PathBuf {
+        self.backup.clone().unwrap_or_else(|| {
+            let mut name = self.path.clone().into_os_string();
+            name.push(".inode");
+            PathBuf::from(name)
+        })
+    }
+
+    fn ours(&self) -> bool {
+        fs::read_to_string(&self.path)
+            .map(|conf| conf.starts_with(RESOLV_CONF_HEADER))
+            .unwrap_or(false)
+    }
+}
+
+impl DhcpResolver for DhcpResolvConfFile {
+    async fn apply(&self, interface: &str, settings: &DhcpDnsSettings) -> io::Result<()> {
+        let backup = self.backup_path();
+        // a backup left from an earlier apply is the original, keep it
+        if !backup.exists() && !self.ours() {
+            match fs::rename(&self.path, &backup) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+        }
+        write_atomic(&self.path, settings.resolv_conf(interface).as_bytes())
+    }
+
+    async fn revert(&self, _interface: &str) -> io::Result<()> {
+        if !self.ours() {
+            return Ok(());
+        }
+        match fs::rename(self.backup_path(), &self.path) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => fs::remove_file(&self.path),
+            result => result,
+        }
+    }
+}
+
+// Hands the settings to resolvconf(8) as the record <interface>.inode.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DhcpResolvconf {
+    pub command: PathBuf,
+}
+
+impl Default for DhcpResolvconf {
+    fn default() -> Self {
+        DhcpResolvconf {
+            command: PathBuf::from("resolvconf"),
+        }
+    }
+}
+
+impl DhcpResolvconf {
+    async fn resolvconf(&self, args: &[&str], input: Option<&str>) -> io::Result<()> {
+        let mut child = Command::new(&self.command)
+            .args(args)
+            .stdin(Stdio::piped())
+            .spawn()?;
+        let mut stdin = child.stdin.take().unwrap();
+        if let Some(input) = input {
+            stdin.write_all(input.as_bytes()).await?;
+        }
+        drop(stdin);
+        let status = child.wait().await?;
+        if !status.success() {
+            return Err(io::Error::other(format!(
+                "{} {} failed: {}",
+                self.command.display(),
+                args.join(" "),
+                status
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl DhcpResolver for DhcpResolvconf {
+    async fn apply(&self, interface: &str, settings: &DhcpDnsSettings) -> io::Result<()> {
+        let record = format!("{}.inode", interface);
+        let conf = settings.resolv_conf(interface);
+        self.resolvconf(&["-a", &record], Some(&conf)).await
+    }
+
+    async fn revert(&self, interface: &str) -> io::Result<()> {
+        let record = format!("{}.inode", interface);
+        self.resolvconf(&["-d", &record], None).await
+    }
+}
+
+// Sets per link DNS servers and domains on systemd-resolved over D-Bus.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DhcpSystemdResolved {}
+
+#[cfg(target_os = "linux")]
+mod resolved {
+    use std::io;
+
+    use super::super::client::find_interface;
+
+    const DESTINATION: &str = "org.freedesktop.resolve1";
+    const PATH: &str = "/org/freedesktop/resolve1";
+    const MANAGER: &str = "org.freedesktop.resolve1.Manager";
+
+    pub fn link_index(interface: &str) -> io::Result<i32> {
+        Ok(find_interface(interface)?.index as i32)
+    }
+
+    pub async fn call<B>(method: &str, body: &B) -> io::Result<()>
+    where
+        B: serde::Serialize + zbus::zvariant::DynamicType,
+    {
+        let connection = zbus::Connection::system().await.map_err(io::Error::other)?;
+        connection
+            .call_method(Some(DESTINATION), PATH, Some(MANAGER), method, body)
+            .await
+            .map_err(io::Error::other)?;
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl DhcpResolver for DhcpSystemdResolved {
+    async fn apply(&self, interface: &str, settings: &DhcpDnsSettings) -> io::Result<()> {
+        // AF_INET with the address bytes, and search domains that are not
+        // routing only
+        const AF_INET: i32 = 2;
+        let index = resolved::link_index(interface)?;
+        let servers: Vec<(i32, Vec<u8>)> = settings
+            .servers
+            .iter()
+            .map(|server| (AF_INET, server.octets().to_vec()))
+            .collect();
+        let domains: Vec<(&str, bool)> = settings
+            .search
+            .iter()
+            .map(|domain| (domain.as_str(), false))
+            .collect();
+        resolved::call("SetLinkDNS", &(index, servers)).await?;
+        resolved::call("SetLinkDomains", &(index, domains)).await
+    }
+
+    async fn revert(&self, interface: &str) -> io::Result<()> {
+        let index = resolved::link_index(interface)?;
+        resolved::call("RevertLink", &(index,)).await
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+impl DhcpResolver for DhcpSystemdResolved {
+    async fn apply(&self, _interface: &str, _settings: &DhcpDnsSettings) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "systemd-resolved is only available on Linux",
+        ))
+    }
+
+    async fn revert(&self, _interface: &str) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,175 @@
+use std::future::Future;
+use std::io;
+use std::time::{Duration, Instant};
+
+use tokio::sync::{broadcast, watch};
+use tokio::task::JoinSet;
+
+use super::client::{
+    find_interface, DhcpClient, DhcpClientConfig, DhcpClientError, DhcpEvent, DHCP_EVENT_CAPACITY,
+};
+use super::raw::DhcpRawSocket;
+use super::transport::{DhcpTransport, DhcpUdpTransport};
+
+// a failed client is restarted after 1, 2, 4 ... 64 seconds, an instance
+// that ran for longer than that starts over at 1
+pub const DHCP_SUPERVISOR_MIN_DELAY_SECS: u64 = 1;
+pub const DHCP_SUPERVISOR_MAX_DELAY_SECS: u64 = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DhcpTransportKind {
+    #[default]
+    Udp,
+    Raw,
+}
+
+// Runs one DhcpClient per interface side by side, each with its own
+// DhcpClientConfig, and restarts the ones that fail until shutdown.
+#[derive(Debug, Clone)]
+pub struct DhcpSupervisor {
+    pub clients: Vec<DhcpClientConfig>,
+    pub transport: DhcpTransportKind,
+    events: broadcast::Sender<(String, DhcpEvent)>,
+}
+
+impl Default for DhcpSupervisor {
+    fn default() -> Self {
+        DhcpSupervisor::new(DhcpTransportKind::default())
+    }
+}
+
+impl DhcpSupervisor {
+    pub fn new(transport: DhcpTransportKind) -> Self {
+        DhcpSupervisor {
+            clients: Vec::new(),
+            transport,
+            events: broadcast::channel(DHCP_EVENT_CAPACITY).0,
+        }
+    }
+
+    // events of every client with the name of its interface, restarted
+    // clients keep sending to the same receivers
+    pub fn subscribe(&self) -> broadcast::Receiver<(String, DhcpEvent)> {
+        self.events.subscribe()
+    }
+
+    // returns once shutdown completed and every client released its lease
+    pub async fn run<F: Future>(self, shutdown: F) {
+        match self.transport {
+            DhcpTransportKind::Udp => {
+                let bind =
+                    |config: &DhcpClientConfig| DhcpUdpTransport::bind(config.interface.as_deref());
+                self.run_with(bind, shutdown).await
+            }
+            // frames go out with the client's address, which may override
+            // the interface's own
+            DhcpTransportKind::Raw => {
+                let bind = |config: &DhcpClientConfig| {
+                    let name = config.interface.as_deref().unwrap_or_default();
+                    find_interface(name)
+                        .and_then(|interface| DhcpRawSocket::bind_interface(interface, config.mac))
+                };
+                self.run_with(bind, shutdown).await
+            }
+        }
+    }
+
+    // run with the transports bind gives each (re)started client
+    pub async fn run_with<T, B, F>(self, bind: B, shutdown: F)
+    where
+        T: DhcpTransport + 'static,
+        B: Fn(&DhcpClientConfig) -> io::Result<T> + Clone + Send + 'static,
+        F: Future,
+    {
+        let (stop, stopped) = watch::channel(false);
+        let mut instances = JoinSet::new();
+        for config in self.clients {
+            instances.spawn(supervise(
+                config,
+                bind.clone(),
+                self.events.clone(),
+                stopped.clone(),
+            ));
+        }
+        tokio::select! {
+            _ = shutdown => {}
+            // every instance ending on its own leaves nothing to supervise
+            _ = async { while instances.join_next().await.is_some() {} } => return,
+        }
+        let _ = stop.send(true);
+        while instances.join_next().await.is_some() {}
+    }
+}
+
+async fn stopped(mut stop: watch::Receiver<bool>) {
+    // a dropped supervisor stops the clients as well
+    let _ = stop.wait_for(|stop| *stop).await;
+}
+
+async fn supervise<T, B>(
+    config: DhcpClientConfig,
+    bind: B,
+    events: broadcast::Sender<(String, DhcpEvent)>,
+    stop: watch::Receiver<bool>,
+) where
+    T: DhcpTransport,
+    B: Fn(&DhcpClientConfig) -> io::Result<T>,
+{
+    let name = config.interface.clone().unwrap_or_default();
+    let mut delay = Duration::from_secs(DHCP_SUPERVISOR_MIN_DELAY_SECS);
+    loop {
+        let started = Instant::now();
+        let result = match bind(&config) {
+            Ok(transport) => run(&config, &transport, &events, stop.clone()).await,
+            Err(e) => Err(DhcpClientError::Io(e)),
+        };
+        let Err(e) = result else {
+            return;
+        };
+        if started.elapsed() > Duration::from_secs(DHCP_SUPERVISOR_MAX_DELAY_SECS) {
+            delay = Duration::from_secs(DHCP_SUPERVISOR_MIN_DELAY_SECS);
+        }
+        println!(
+            "[{}] DHCP client failed: {}, restarting in {:?}",
+            name, e, delay
+        );
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = stopped(stop.clone()) => return,
+        }
+        delay = (delay * 2).min(Duration::from_secs(DHCP_SUPERVISOR_MAX_DELAY_SECS));
+    }
+}
+
+async fn run<T: DhcpTransport>(
+    config: &DhcpClientConfig,
+    transport: &T,
+    events: &broadcast::Sender<(String, DhcpEvent)>,
+    stop: watch::Receiver<bool>,
+) -> Result<(), DhcpClientError> {
+    let name = config.interface.clone().unwrap_or_default();
+    let mut client = DhcpClient::new(config.clone());
+    let forward = tokio::spawn(forward(client.subscribe(), name, events.clone()));
+    let result = client.run(transport, stopped(stop)).await;
+    // dropping the client closes its channel once the last events are through
+    drop(client);
+    let _ = forward.await;
+    result
+}
+
+async fn forward(
+    mut received: broadcast::Receiver<DhcpEvent>,
+    name: String,
+    events: broadcast::Sender<(String, DhcpEvent)>,
+) {
+    loop {
+        match received.recv().await {
+            // nobody listening is fine
+            Ok(event) => {
+                let _ = events.send((name.clone(), event));
+            }
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => return,
+        }
+    }
+}
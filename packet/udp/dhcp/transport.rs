@@ -0,0 +1,194 @@
+use std::future::Future;
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::time::Instant;
+
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::UdpSocket;
+use tokio::sync::{mpsc, Mutex};
+
+use super::client::{DhcpDestination, DHCP_CLIENT_PORT, DHCP_SERVER_PORT};
+use super::raw::DhcpRawSocket;
+
+// How DhcpClient reaches the servers. Receiving waits until the deadline
+// and returns None once it passed.
+pub trait DhcpTransport: Send + Sync {
+    // name of the interface the transport is bound to
+    fn interface(&self) -> Option<&str>;
+
+    fn send_to(
+        &self,
+        buf: &[u8],
+        destination: DhcpDestination,
+    ) -> impl Future<Output = io::Result<()>> + Send;
+
+    fn recv_from(
+        &self,
+        buf: &mut [u8],
+        deadline: Option<Instant>,
+    ) -> impl Future<Output = io::Result<Option<(usize, SocketAddr)>>> + Send;
+}
+
+async fn until<T, F>(deadline: Option<Instant>, received: F) -> io::Result<Option<T>>
+where
+    F: Future<Output = io::Result<T>>,
+{
+    match deadline {
+        Some(deadline) => match tokio::time::timeout_at(deadline.into(), received).await {
+            Ok(received) => received.map(Some),
+            Err(_) => Ok(None),
+        },
+        None => received.await.map(Some),
+    }
+}
+
+// the host stack's UDP socket on the DHCP client port
+pub struct DhcpUdpTransport {
+    socket: UdpSocket,
+    interface: Option<String>,
+}
+
+impl DhcpUdpTransport {
+    // socket should be bound to DHCP_CLIENT_PORT with broadcast enabled, on
+    // Linux it is tied to interface so replies from other ports are not seen
+    pub fn new(socket: UdpSocket, interface: Option<&str>) -> io::Result<Self> {
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        if let Some(name) = interface {
+            socket.bind_device(Some(name.as_bytes()))?;
+        }
+        Ok(DhcpUdpTransport {
+            socket,
+            interface: interface.map(str::to_string),
+        })
+    }
+
+    // clients on different interfaces each bind the client port, which the
+    // device binding and SO_REUSEADDR allow
+    pub fn bind(interface: Option<&str>) -> io::Result<Self> {
+        let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+        socket.set_reuse_address(true)?;
+        socket.set_broadcast(true)?;
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        socket.bind_device(interface.map(str::as_bytes))?;
+        socket.set_nonblocking(true)?;
+        socket.bind(&SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, DHCP_CLIENT_PORT).into())?;
+        Ok(DhcpUdpTransport {
+            socket: UdpSocket::from_std(socket.into())?,
+            interface: interface.map(str::to_string),
+        })
+    }
+}
+
+impl DhcpTransport for DhcpUdpTransport {
+    fn interface(&self) -> Option<&str> {
+        self.interface.as_deref()
+    }
+
+    async fn send_to(&self, buf: &[u8], destination: DhcpDestination) -> io::Result<()> {
+        self.socket.send_to(buf, destination.addr()).await?;
+        Ok(())
+    }
+
+    async fn recv_from(
+        &self,
+        buf: &mut [u8],
+        deadline: Option<Instant>,
+    ) -> io::Result<Option<(usize, SocketAddr)>> {
+        until(deadline, self.socket.recv_from(buf)).await
+    }
+}
+
+impl DhcpTransport for DhcpRawSocket {
+    fn interface(&self) -> Option<&str> {
+        Some(&self.network_interface().name)
+    }
+
+    async fn send_to(&self, buf: &[u8], destination: DhcpDestination) -> io::Result<()> {
+        DhcpRawSocket::send_to(self, buf, destination.addr()).await?;
+        Ok(())
+    }
+
+    async fn recv_from(
+        &self,
+        buf: &mut [u8],
+        deadline: Option<Instant>,
+    ) -> io::Result<Option<(usize, SocketAddr)>> {
+        until(deadline, DhcpRawSocket::recv_from(self, buf)).await
+    }
+}
+
+// Client end of an in-process link to a DhcpMemoryServer, for driving the
+// client without a network.
+pub struct DhcpMemoryTransport {
+    interface: Option<String>,
+    tx: mpsc::UnboundedSender<(Vec<u8>, DhcpDestination)>,
+    rx: Mutex<mpsc::UnboundedReceiver<(Vec<u8>, SocketAddr)>>,
+}
+
+// server end of a DhcpMemoryTransport
+pub struct DhcpMemoryServer {
+    tx: mpsc::UnboundedSender<(Vec<u8>, SocketAddr)>,
+    rx: mpsc::UnboundedReceiver<(Vec<u8>, DhcpDestination)>,
+}
+
+impl DhcpMemoryTransport {
+    pub fn pair(interface: Option<&str>) -> (DhcpMemoryTransport, DhcpMemoryServer) {
+        let (client_tx, server_rx) = mpsc::unbounded_channel();
+        let (server_tx, client_rx) = mpsc::unbounded_channel();
+        (
+            DhcpMemoryTransport {
+                interface: interface.map(str::to_string),
+                tx: client_tx,
+                rx: Mutex::new(client_rx),
+            },
+            DhcpMemoryServer {
+                tx: server_tx,
+                rx: server_rx,
+            },
+        )
+    }
+}
+
+impl DhcpTransport for DhcpMemoryTransport {
+    fn interface(&self) -> Option<&str> {
+        self.interface.as_deref()
+    }
+
+    async fn send_to(&self, buf: &[u8], destination: DhcpDestination) -> io::Result<()> {
+        self.tx
+            .send((buf.to_vec(), destination))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "DHCP server went away"))
+    }
+
+    async fn recv_from(
+        &self,
+        buf: &mut [u8],
+        deadline: Option<Instant>,
+    ) -> io::Result<Option<(usize, SocketAddr)>> {
+        let mut rx = self.rx.lock().await;
+        let received = async {
+            let (datagram, source) = rx.recv().await.ok_or_else(|| {
+                io::Error::new(io::ErrorKind::BrokenPipe, "DHCP server went away")
+            })?;
+            let len = datagram.len().min(buf.len());
+            buf[..len].copy_from_slice(&datagram[..len]);
+            Ok((len, source))
+        };
+        until(deadline, received).await
+    }
+}
+
+impl DhcpMemoryServer {
+    // next datagram sent by the client, None once the client is dropped
+    pub async fn recv(&mut self) -> Option<(Vec<u8>, DhcpDestination)> {
+        self.rx.recv().await
+    }
+
+    // delivers a datagram from server to the client
+    pub fn send(&self, server: Ipv4Addr, datagram: &[u8]) -> io::Result<()> {
+        let source = SocketAddr::V4(SocketAddrV4::new(server, DHCP_SERVER_PORT));
+        self.tx
+            .send((datagram.to_vec(), source))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "DHCP client went away"))
+    }
+}